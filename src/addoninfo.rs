@@ -0,0 +1,56 @@
+use crate::keyvalues;
+use crate::util;
+use crate::vpk::{Vpk, VpkError};
+
+use std::path::Path;
+
+/// The addoninfo.txt that addons include to describe themselves in the game's addon list
+pub struct AddonInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub version: Option<String>,
+    /// The addonContent_* flags that are set, without the prefix (ie "Campaign", "Skin")
+    pub content: Vec<String>,
+    /// Workshop id of the addon's own page, from addonURL0
+    pub workshop_id: Option<String>
+}
+
+const CONTENT_PREFIX: &str = "addoncontent_";
+
+impl AddonInfo {
+    /// Reads addoninfo.txt from a VPK. Returns None if the VPK has none, or it cannot be parsed
+    pub fn from_vpk(path: &Path) -> Result<Option<AddonInfo>, VpkError> {
+        let vpk = Vpk::open(path)?;
+        let entry = match vpk.get("addoninfo.txt") {
+            Some(entry) => entry,
+            None => return Ok(None)
+        };
+        let contents = vpk.read_file(entry)?;
+        Ok(AddonInfo::parse(&String::from_utf8_lossy(&contents)))
+    }
+
+    pub fn parse(contents: &str) -> Option<AddonInfo> {
+        let entries = keyvalues::parse(contents).ok()?;
+        let root = entries.iter().find(|entry| entry.key.eq_ignore_ascii_case("AddonInfo"))?;
+        let children = root.children()?;
+        let get = |key: &str| -> Option<String> {
+            root.get_str(key).map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+        };
+
+        let content: Vec<String> = children.iter()
+            .filter(|child| child.key.to_ascii_lowercase().starts_with(CONTENT_PREFIX))
+            .filter(|child| child.as_str().map(|value| value.trim() != "0" && !value.trim().is_empty()).unwrap_or(false))
+            .map(|child| child.key[CONTENT_PREFIX.len()..].to_string())
+            .collect();
+        //Only addonURL0 is the addon's own page, the other urls often link to required addons or the author's other mods
+        let workshop_id = root.get_str("addonurl0").and_then(util::Regexes::get_id_from_workshop_url);
+
+        Some(AddonInfo {
+            title: get("addontitle"),
+            author: get("addonauthor"),
+            version: get("addonversion"),
+            content,
+            workshop_id
+        })
+    }
+}
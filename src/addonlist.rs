@@ -0,0 +1,67 @@
+use crate::keyvalues::{self, KeyValue, Value};
+
+use std::{fs, path::{Path, PathBuf}};
+
+/// The enabled state of each addon, stored by the game in left4dead2/addonlist.txt.
+/// Addons that are not listed are enabled
+pub struct AddonList {
+    path: PathBuf,
+    entries: Vec<KeyValue>
+}
+
+impl AddonList {
+    /// Gets the path of addonlist.txt, which is next to the addons folder
+    pub fn get_path(gamedir: &Path) -> PathBuf {
+        gamedir.parent().unwrap_or(gamedir).join("addonlist.txt")
+    }
+
+    /// Loads the addon list for the addons folder. Returns an empty list if the file does not exist yet
+    pub fn load(gamedir: &Path) -> Result<AddonList, Box<dyn std::error::Error>> {
+        let path = AddonList::get_path(gamedir);
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => keyvalues::parse(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into())
+        };
+        Ok(AddonList {
+            path,
+            entries
+        })
+    }
+
+    pub fn save(&self) -> Result<(), std::io::Error> {
+        fs::write(&self.path, keyvalues::to_string(&self.entries))
+    }
+
+    fn get_root(&self) -> Option<&KeyValue> {
+        self.entries.iter().find(|entry| entry.key.eq_ignore_ascii_case("AddonList"))
+    }
+
+    fn get_root_mut(&mut self) -> &mut KeyValue {
+        let index = match self.entries.iter().position(|entry| entry.key.eq_ignore_ascii_case("AddonList")) {
+            Some(index) => index,
+            None => {
+                self.entries.push(KeyValue::new("AddonList", Value::Section(Vec::new())));
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[index]
+    }
+
+    /// Checks if the vpk (ie "123.vpk", relative to the addons folder) is enabled
+    pub fn is_enabled(&self, filename: &str) -> bool {
+        match self.get_root().and_then(|root| root.get_str(filename)) {
+            Some(value) => value.trim() != "0",
+            None => true
+        }
+    }
+
+    /// Enables or disables the vpk (ie "123.vpk", relative to the addons folder)
+    pub fn set_enabled(&mut self, filename: &str, enabled: bool) {
+        let root = self.get_root_mut();
+        if root.children().is_none() {
+            root.value = Value::Section(Vec::new());
+        }
+        root.set_str(filename, if enabled { "1" } else { "0" });
+    }
+}
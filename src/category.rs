@@ -0,0 +1,124 @@
+use crate::vpk::Vpk;
+
+use std::{fmt, path::Path};
+
+/// What kind of content an addon adds. Ordered by how specific the category is, which is also the sort order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Category {
+    Campaign,
+    Survivor,
+    Infected,
+    Weapon,
+    Script,
+    Sound,
+    UI,
+    Model,
+    Other
+}
+
+pub const CATEGORIES: [Category; 9] = [
+    Category::Campaign,
+    Category::Survivor,
+    Category::Infected,
+    Category::Weapon,
+    Category::Script,
+    Category::Sound,
+    Category::UI,
+    Category::Model,
+    Category::Other
+];
+
+/// Workshop tags (lowercase) and the category they indicate
+const TAG_CATEGORIES: [(&str, Category); 27] = [
+    ("campaigns", Category::Campaign),
+    ("maps", Category::Campaign),
+    ("survivors", Category::Survivor),
+    ("bill", Category::Survivor),
+    ("francis", Category::Survivor),
+    ("louis", Category::Survivor),
+    ("zoey", Category::Survivor),
+    ("coach", Category::Survivor),
+    ("ellis", Category::Survivor),
+    ("nick", Category::Survivor),
+    ("rochelle", Category::Survivor),
+    ("common infected", Category::Infected),
+    ("special infected", Category::Infected),
+    ("boomer", Category::Infected),
+    ("charger", Category::Infected),
+    ("hunter", Category::Infected),
+    ("jockey", Category::Infected),
+    ("smoker", Category::Infected),
+    ("spitter", Category::Infected),
+    ("tank", Category::Infected),
+    ("witch", Category::Infected),
+    ("weapons", Category::Weapon),
+    ("items", Category::Weapon),
+    ("scripts", Category::Script),
+    ("sounds", Category::Sound),
+    ("ui", Category::UI),
+    ("models", Category::Model)
+];
+
+/// Folders inside a VPK (lowercase) and the category they indicate, most specific first
+const PATH_CATEGORIES: [(&str, Category); 12] = [
+    ("missions/", Category::Campaign),
+    ("maps/", Category::Campaign),
+    ("models/survivors/", Category::Survivor),
+    ("models/infected/", Category::Infected),
+    ("models/weapons/", Category::Weapon),
+    ("models/v_models/", Category::Weapon),
+    ("models/w_models/", Category::Weapon),
+    ("scripts/vscripts/", Category::Script),
+    ("sound/", Category::Sound),
+    ("resource/", Category::UI),
+    ("materials/vgui/", Category::UI),
+    ("models/", Category::Model)
+];
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Category::Campaign => "Campaign",
+            Category::Survivor => "Survivor",
+            Category::Infected => "Infected",
+            Category::Weapon => "Weapon",
+            Category::Script => "Script",
+            Category::Sound => "Sound",
+            Category::UI => "UI",
+            Category::Model => "Model",
+            Category::Other => "Other"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Gets the most specific category that the workshop tags indicate
+pub fn from_tags(tags: &[String]) -> Option<Category> {
+    tags.iter()
+        .filter_map(|tag| {
+            let tag = tag.to_ascii_lowercase();
+            TAG_CATEGORIES.iter().find(|(name, _)| *name == tag).map(|(_, category)| *category)
+        })
+        .min()
+}
+
+/// Gets the most specific category that the files in a VPK indicate
+pub fn from_vpk(path: &Path) -> Option<Category> {
+    let vpk = Vpk::open(path).ok()?;
+    vpk.entries.iter()
+        .filter_map(|entry| {
+            let path = entry.path.to_ascii_lowercase();
+            PATH_CATEGORIES.iter().find(|(folder, _)| path.starts_with(folder)).map(|(_, category)| *category)
+        })
+        .min()
+}
+
+/// Picks the category of an addon, trusting the workshop tags over the VPK's contents
+pub fn classify(tag_category: Option<Category>, vpk_category: Option<Category>) -> Category {
+    match (tag_category, vpk_category) {
+        (Some(Category::Model), Some(category)) => category.min(Category::Model),
+        (Some(category), _) => category,
+        (None, Some(category)) => category,
+        (None, None) => Category::Other
+    }
+}
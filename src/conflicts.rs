@@ -0,0 +1,207 @@
+use crate::util;
+use crate::addonlist::AddonList;
+use crate::logger::LogLevel;
+use crate::vpk::Vpk;
+
+use prettytable::{Table, Row, Cell, row};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use steam_workshop_api::Workshop;
+
+/// Files every addon has, which the game never loads from the addon itself
+const IGNORED_FILES: [&str; 3] = ["addoninfo.txt", "addonimage.jpg", "addonimage.vtf"];
+/// The types conflicts are grouped by, matched against the first folder of the path
+const CATEGORIES: [&str; 6] = ["models", "materials", "scripts", "missions", "sound", "maps"];
+const OTHER_CATEGORY: &str = "other";
+
+/// A file that more than one addon contains
+#[derive(Serialize)]
+pub struct Conflict {
+    pub path: String,
+    /// Filenames of the addons that contain the file
+    pub addons: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct ConflictGroup {
+    pub category: &'static str,
+    pub conflicts: Vec<Conflict>
+}
+
+/// An addon that could not be indexed
+#[derive(Serialize)]
+pub struct UnreadableAddon {
+    pub file: String,
+    pub error: String
+}
+
+#[derive(Serialize)]
+pub struct ConflictReport {
+    /// Number of addons that were scanned
+    pub scanned: usize,
+    pub unreadable: Vec<UnreadableAddon>,
+    pub groups: Vec<ConflictGroup>
+}
+
+impl ConflictReport {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+/// Shows the conflicts in the manage menu
+pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
+    let report = scan(menu)?;
+    print_report(menu, &report);
+    Ok(None)
+}
+
+/// Prints the conflicts without the menu, as a table or as JSON. Returns one of the util::exit_code values
+pub fn command(menu: &mut util::MenuParams, json: bool) -> i32 {
+    let report = match scan(menu) {
+        Ok(report) => report,
+        Err(err) => {
+            menu.logger.error("CmdConflicts/scan", &err.to_string());
+            return util::exit_code::ERROR
+        }
+    };
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                menu.logger.error("CmdConflicts/serialize", &err.to_string());
+                return util::exit_code::ERROR
+            }
+        }
+    } else {
+        print_report(menu, &report);
+    }
+    if report.is_empty() {
+        util::exit_code::NOTHING_TO_DO
+    } else {
+        util::exit_code::UPDATED
+    }
+}
+
+/// Gets the category of a path inside a VPK, ie "models" for "models/props/barrel.mdl"
+pub fn get_category(path: &str) -> &'static str {
+    let folder = path.split('/').next().unwrap_or_default();
+    CATEGORIES.iter()
+        .find(|category| folder.eq_ignore_ascii_case(category))
+        .copied()
+        .unwrap_or(OTHER_CATEGORY)
+}
+
+/// Indexes the files of every enabled VPK in the addons folder and finds the ones that more than one addon contains
+pub fn scan(menu: &util::MenuParams) -> Result<ConflictReport, Box<dyn std::error::Error>> {
+    let filenames = Workshop::get_vpks_in_folder(&menu.config.gamedir)?;
+    //Disabled addons are not loaded, so they cannot conflict
+    let filenames: Vec<String> = match AddonList::load(&menu.config.gamedir) {
+        Ok(addonlist) => filenames.into_iter().filter(|filename| addonlist.is_enabled(&format!("{}.vpk", filename))).collect(),
+        Err(err) => {
+            menu.logger.warn("Conflicts/AddonList", &format!("Could not read {}, checking every addon: {}", AddonList::get_path(&menu.config.gamedir).display(), err));
+            filenames
+        }
+    };
+
+    let spinner = util::setup_spinner(format!("Indexing {} addons...", filenames.len()));
+    let gamedir = &menu.config.gamedir;
+    let results: Vec<(String, Result<Vec<String>, String>)> = filenames
+        .par_iter()
+        .map(|filename| {
+            let file = format!("{}.vpk", filename);
+            let paths = Vpk::open(&gamedir.join(&file))
+                .map(|vpk| vpk.entries.into_iter().map(|entry| entry.path.to_ascii_lowercase()).collect())
+                .map_err(|err| err.to_string());
+            (file, paths)
+        })
+        .collect();
+    spinner.finish_and_clear();
+
+    let mut index: HashMap<String, Vec<String>> = HashMap::new();
+    let mut unreadable: Vec<UnreadableAddon> = Vec::new();
+    for (file, paths) in results {
+        match paths {
+            Ok(paths) => {
+                for path in paths {
+                    if IGNORED_FILES.contains(&path.as_str()) {
+                        continue
+                    }
+                    index.entry(path).or_default().push(file.clone());
+                }
+            },
+            Err(err) => {
+                menu.logger.logp(LogLevel::WARN, "Conflicts", &format!("Could not read {}: {}", file, err));
+                unreadable.push(UnreadableAddon { file, error: err });
+            }
+        }
+    }
+
+    let mut groups: Vec<ConflictGroup> = Vec::new();
+    let mut conflicts: Vec<Conflict> = index.into_iter()
+        .filter(|(_, addons)| addons.len() > 1)
+        .map(|(path, addons)| Conflict { path, addons })
+        .collect();
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+    for category in CATEGORIES.iter().copied().chain(std::iter::once(OTHER_CATEGORY)) {
+        let (matching, rest): (Vec<Conflict>, Vec<Conflict>) = conflicts.into_iter().partition(|conflict| get_category(&conflict.path) == category);
+        conflicts = rest;
+        if !matching.is_empty() {
+            groups.push(ConflictGroup { category, conflicts: matching });
+        }
+    }
+
+    Ok(ConflictReport {
+        scanned: filenames.len(),
+        unreadable,
+        groups
+    })
+}
+
+/// Gets the tracked title of an addon, falling back to its filename
+fn get_label(menu: &util::MenuParams, file: &str) -> String {
+    let stem = file.trim_end_matches(".vpk");
+    match crate::meta::get_filename_id(stem).and_then(|id| menu.config.get_download(id)) {
+        Some(entry) => entry.title.clone(),
+        None => file.to_string()
+    }
+}
+
+/// Prints how many files each set of addons overrides in each category
+fn print_report(menu: &util::MenuParams, report: &ConflictReport) {
+    for addon in &report.unreadable {
+        println!("{} could not be read: {}", addon.file, addon.error);
+    }
+    if report.is_empty() {
+        println!("{}", console::style(format!("No conflicts were found between {} addons.", report.scanned)).green());
+        return
+    }
+
+    println!("{}", console::style("Conflicts").bold());
+    let mut table = Table::new();
+    table.set_titles(row!["Addons", "Type", "Files", "Example"]);
+    let mut total: usize = 0;
+    for group in &report.groups {
+        //Summarise by the set of addons, as they usually share many files
+        let mut sets: Vec<(&Vec<String>, usize, &str)> = Vec::new();
+        for conflict in &group.conflicts {
+            match sets.iter_mut().find(|(addons, _, _)| *addons == &conflict.addons) {
+                Some(set) => set.1 += 1,
+                None => sets.push((&conflict.addons, 1, &conflict.path))
+            }
+        }
+        for (addons, count, example) in sets {
+            let labels: Vec<String> = addons.iter().map(|file| get_label(menu, file)).collect();
+            table.add_row(Row::new(vec![
+                Cell::new(&labels.join("\n")),
+                Cell::new(group.category),
+                Cell::new(&count.to_string()),
+                Cell::new(example)
+            ]));
+        }
+        total += group.conflicts.len();
+    }
+    table.printstd();
+    println!("{} files are replaced by more than one addon. Use the conflicts subcommand with --json for the full list.", total);
+}
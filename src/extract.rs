@@ -0,0 +1,150 @@
+use crate::util;
+use crate::logger::LogLevel;
+use crate::vpk::{Vpk, VpkEntry};
+
+use regex::Regex;
+use std::{fs, io, path::{Component, Path, PathBuf}};
+
+/// Extracts the files of an addon that match `pattern` into `out`, returning one of the util::exit_code values.
+/// `input` is a workshop id or url of a tracked addon, or the path of a VPK (ie "pak01_dir.vpk" for multi-chunk VPKs)
+pub fn command(menu: &mut util::MenuParams, input: &str, pattern: Option<&str>, out: &Path) -> i32 {
+    let path = get_vpk_path(menu, input);
+    let vpk = match Vpk::open(&path) {
+        Ok(vpk) => vpk,
+        Err(err) => {
+            menu.logger.error("CmdExtract/open", &format!("Could not read {}: {}", path.display(), err));
+            return util::exit_code::ERROR
+        }
+    };
+    let matcher = match pattern.map(glob_to_regex).transpose() {
+        Ok(matcher) => matcher,
+        Err(err) => {
+            menu.logger.error("CmdExtract/pattern", &format!("Invalid pattern: {}", err));
+            return util::exit_code::ERROR
+        }
+    };
+    let entries: Vec<&VpkEntry> = vpk.entries.iter()
+        .filter(|entry| match &matcher {
+            Some(matcher) => is_match(matcher, pattern.unwrap_or_default(), &entry.path),
+            None => true
+        })
+        .collect();
+    if entries.is_empty() {
+        println!("No files in {} match the pattern.", path.display());
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    let mut extracted: usize = 0;
+    let mut bytes: u64 = 0;
+    let mut failed: usize = 0;
+    for entry in entries {
+        let dest = match get_safe_path(out, &entry.path) {
+            Some(dest) => dest,
+            None => {
+                menu.logger.warn("CmdExtract", &format!("Skipped {}, its path would escape the output folder", entry.path));
+                failed += 1;
+                continue
+            }
+        };
+        if let Err(err) = extract_entry(&vpk, entry, &dest) {
+            menu.logger.warn("CmdExtract", &format!("Could not extract {}: {}", entry.path, err));
+            failed += 1;
+            continue
+        }
+        extracted += 1;
+        bytes += entry.size();
+    }
+
+    println!("Extracted {} files ({}) to {}", extracted, util::format_bytes(bytes), out.display());
+    menu.logger.logp(LogLevel::INFO, "CmdExtract", &format!("Extracted {} files from {} to {}, {} failed", extracted, path.display(), out.display(), failed));
+    if failed == 0 {
+        util::exit_code::UPDATED
+    } else if extracted == 0 {
+        util::exit_code::ERROR
+    } else {
+        util::exit_code::PARTIAL_FAILURE
+    }
+}
+
+/// Resolves the input to a VPK: a tracked addon's id or url, a path, or a file in the addons folder
+fn get_vpk_path(menu: &util::MenuParams, input: &str) -> PathBuf {
+    if let Some(id) = util::Regexes::get_id_from_input(input) {
+        if menu.config.get_download(&id).is_some() || !Path::new(input).exists() {
+            return menu.config.get_addon_path(&id)
+        }
+    }
+    let path = PathBuf::from(input);
+    if path.exists() {
+        return path
+    }
+    let in_gamedir = menu.config.gamedir.join(input);
+    if in_gamedir.extension().is_none() {
+        in_gamedir.with_extension("vpk")
+    } else {
+        in_gamedir
+    }
+}
+
+fn extract_entry(vpk: &Vpk, entry: &VpkEntry, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = io::BufWriter::new(fs::File::create(dest)?);
+    vpk.copy_file(entry, &mut file)?;
+    io::Write::flush(&mut file)?;
+    Ok(())
+}
+
+/// Joins a path from inside a VPK onto the output folder, returning None if it is absolute or climbs out with ".."
+fn get_safe_path(out: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path);
+    if path.contains('\\') || path.contains(':') {
+        return None
+    }
+    let mut dest = out.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => dest.push(part),
+            Component::CurDir => {},
+            _ => return None
+        }
+    }
+    if dest == out {
+        return None
+    }
+    Some(dest)
+}
+
+/// Converts a glob to a case insensitive regex. "*" and "?" stay within a folder, "**" matches across folders
+fn glob_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let pattern = pattern.replace('\\', "/");
+    let mut regex = String::from("(?i)^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            },
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string()))
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex)
+}
+
+/// Patterns without a folder, like "*.mdl", match the file name in any folder
+fn is_match(matcher: &Regex, pattern: &str, path: &str) -> bool {
+    if pattern.contains('/') || pattern.contains('\\') {
+        matcher.is_match(path)
+    } else {
+        matcher.is_match(path.rsplit('/').next().unwrap_or(path))
+    }
+}
@@ -0,0 +1,317 @@
+//! Reader and writer for Valve's KeyValues text format (addonlist.txt, libraryfolders.vdf, ...).
+//! Entries keep their order, duplicates and conditions so files can be written back without losing anything but comments.
+//! Quoted strings use backslash escapes (\", \\, \n and \t).
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyValue {
+    pub key: String,
+    pub value: Value,
+    /// Platform condition following the entry, ie "[$WIN32]"
+    pub condition: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Section(Vec<KeyValue>)
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl KeyValue {
+    pub fn new(key: &str, value: Value) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value,
+            condition: None
+        }
+    }
+
+    /// Gets the value as a string, if it is not a section
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::String(value) => Some(value),
+            Value::Section(_) => None
+        }
+    }
+
+    /// Gets the children of this entry, if it is a section
+    pub fn children(&self) -> Option<&Vec<KeyValue>> {
+        match &self.value {
+            Value::Section(children) => Some(children),
+            Value::String(_) => None
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<KeyValue>> {
+        match &mut self.value {
+            Value::Section(children) => Some(children),
+            Value::String(_) => None
+        }
+    }
+
+    /// Gets the first child with the given key. Keys are case insensitive
+    pub fn get(&self, key: &str) -> Option<&KeyValue> {
+        self.children()?.iter().find(|child| child.key.eq_ignore_ascii_case(key))
+    }
+
+    /// Gets the string value of the first child with the given key
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+
+    /// Sets the string value of the first child with the given key, adding it to the end if it does not exist.
+    /// Does nothing if this entry is not a section
+    pub fn set_str(&mut self, key: &str, value: &str) {
+        if let Some(children) = self.children_mut() {
+            match children.iter_mut().find(|child| child.key.eq_ignore_ascii_case(key)) {
+                Some(child) => child.value = Value::String(value.to_string()),
+                None => children.push(KeyValue::new(key, Value::String(value.to_string())))
+            }
+        }
+    }
+}
+
+/// Parses every top level entry in the input
+pub fn parse(input: &str) -> Result<Vec<KeyValue>, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+        line: 1
+    };
+    parser.parse_entries(false)
+}
+
+/// Writes the entries in the same layout the game uses
+pub fn to_string(entries: &[KeyValue]) -> String {
+    let mut output = String::new();
+    write_entries(&mut output, entries, 0);
+    output
+}
+
+fn write_entries(output: &mut String, entries: &[KeyValue], depth: usize) {
+    let indent = "\t".repeat(depth);
+    for entry in entries {
+        match &entry.value {
+            Value::String(value) => {
+                output.push_str(&format!("{}\"{}\"\t\t\"{}\"", indent, escape(&entry.key), escape(value)));
+                if let Some(condition) = &entry.condition {
+                    output.push_str(&format!(" {}", condition));
+                }
+                output.push('\n');
+            },
+            Value::Section(children) => {
+                output.push_str(&format!("{}\"{}\"", indent, escape(&entry.key)));
+                if let Some(condition) = &entry.condition {
+                    output.push_str(&format!(" {}", condition));
+                }
+                output.push_str(&format!("\n{}{{\n", indent));
+                write_entries(output, children, depth + 1);
+                output.push_str(&format!("{}}}\n", indent));
+            }
+        }
+    }
+}
+
+/// Escapes a string to be written between quotes, the reverse of what next_token does when reading it
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize
+}
+
+enum Token {
+    String(String),
+    Condition(String),
+    Open,
+    Close
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            message: message.to_string()
+        }
+    }
+
+    fn parse_entries(&mut self, nested: bool) -> Result<Vec<KeyValue>, ParseError> {
+        let mut entries: Vec<KeyValue> = Vec::new();
+        let mut pending = self.next_token()?;
+        loop {
+            let key = match pending {
+                Some(Token::String(key)) => key,
+                Some(Token::Close) if nested => return Ok(entries),
+                Some(Token::Close) => return Err(self.error("Unexpected '}'")),
+                Some(Token::Open) => return Err(self.error("Expected a key, found '{'")),
+                Some(Token::Condition(_)) => return Err(self.error("Expected a key, found a condition")),
+                None if nested => return Err(self.error("Expected '}', found end of file")),
+                None => return Ok(entries)
+            };
+
+            let mut condition: Option<String> = None;
+            let mut token = self.next_token()?;
+            if let Some(Token::Condition(cond)) = token {
+                condition = Some(cond);
+                token = self.next_token()?;
+            }
+            let value = match token {
+                Some(Token::String(value)) => Value::String(value),
+                Some(Token::Open) => Value::Section(self.parse_entries(true)?),
+                _ => return Err(self.error(&format!("Expected a value for \"{}\"", key)))
+            };
+
+            pending = self.next_token()?;
+            if let Some(Token::Condition(cond)) = pending {
+                condition = Some(cond);
+                pending = self.next_token()?;
+            }
+            entries.push(KeyValue { key, value, condition });
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, ParseError> {
+        loop {
+            let c = match self.chars.next() {
+                Some(c) => c,
+                None => return Ok(None)
+            };
+            match c {
+                '\n' => self.line += 1,
+                c if c.is_whitespace() => {},
+                '/' if self.chars.peek() == Some(&'/') => {
+                    //Comment, skip to the end of the line
+                    while let Some(c) = self.chars.peek() {
+                        if *c == '\n' {
+                            break
+                        }
+                        self.chars.next();
+                    }
+                },
+                '{' => return Ok(Some(Token::Open)),
+                '}' => return Ok(Some(Token::Close)),
+                '"' => {
+                    let mut value = String::new();
+                    loop {
+                        match self.chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match self.chars.next() {
+                                Some('"') => value.push('"'),
+                                Some('\\') => value.push('\\'),
+                                Some('n') => value.push('\n'),
+                                Some('t') => value.push('\t'),
+                                //Unknown escapes are kept as written, ie the "\m" in "maps\mymap"
+                                Some(c) => {
+                                    if c == '\n' {
+                                        self.line += 1;
+                                    }
+                                    value.push('\\');
+                                    value.push(c);
+                                },
+                                None => return Err(self.error("Unterminated string"))
+                            },
+                            Some('\n') => {
+                                self.line += 1;
+                                value.push('\n');
+                            },
+                            Some(c) => value.push(c),
+                            None => return Err(self.error("Unterminated string"))
+                        }
+                    }
+                    return Ok(Some(Token::String(value)))
+                },
+                '[' => {
+                    let mut condition = String::from("[");
+                    loop {
+                        match self.chars.next() {
+                            Some(']') => break,
+                            Some(c) => condition.push(c),
+                            None => return Err(self.error("Unterminated condition"))
+                        }
+                    }
+                    condition.push(']');
+                    return Ok(Some(Token::Condition(condition)))
+                },
+                c => {
+                    //Unquoted string, ends at whitespace or a brace
+                    let mut value = String::new();
+                    value.push(c);
+                    while let Some(c) = self.chars.peek() {
+                        if c.is_whitespace() || *c == '{' || *c == '}' || *c == '"' {
+                            break
+                        }
+                        value.push(*c);
+                        self.chars.next();
+                    }
+                    return Ok(Some(Token::String(value)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the input, writes it back and parses that again, checking nothing changed along the way
+    fn round_trip(input: &str) -> Vec<KeyValue> {
+        let entries = parse(input).unwrap();
+        let written = to_string(&entries);
+        assert_eq!(parse(&written).unwrap(), entries, "{}", written);
+        entries
+    }
+
+    #[test]
+    fn reads_and_writes_escaped_quotes() {
+        let entries = round_trip("\"addoninfo\"\n{\n\t\"addontitle\"\t\"The \\\"Best\\\" Map\"\n}\n");
+        assert_eq!(entries[0].get_str("addontitle"), Some("The \"Best\" Map"));
+        assert!(to_string(&entries).contains("\"The \\\"Best\\\" Map\""));
+    }
+
+    #[test]
+    fn reads_and_writes_escaped_backslashes() {
+        let entries = round_trip("\"libraryfolders\"\n{\n\t\"path\"\t\"C:\\\\Program Files (x86)\\\\Steam\"\n}\n");
+        assert_eq!(entries[0].get_str("path"), Some("C:\\Program Files (x86)\\Steam"));
+    }
+
+    #[test]
+    fn keeps_unknown_escapes() {
+        let entries = round_trip("\"map\"\t\"maps\\mymap\"\n\"lines\"\t\"one\\ntwo\"\n");
+        assert_eq!(entries[0].as_str(), Some("maps\\mymap"));
+        assert_eq!(entries[1].as_str(), Some("one\ntwo"));
+    }
+
+    #[test]
+    fn keeps_conditions_and_order() {
+        let input = "\"AddonList\"\n{\n\t\"b.vpk\"\t\t\"1\"\n\t\"a.vpk\"\t\t\"0\" [$WIN32]\n}\n";
+        let entries = round_trip(input);
+        assert_eq!(to_string(&entries), input);
+    }
+}
@@ -11,6 +11,7 @@ pub struct Logger {
 }
 
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum LogLevel {
     ERROR,
     WARN,
@@ -22,6 +23,9 @@ pub enum LogLevel {
 #[allow(dead_code)]
 impl Logger {
     pub fn new(filepath: path::PathBuf) -> Logger {
+        if let Some(parent) = filepath.parent() {
+            fs::create_dir_all(parent).ok();
+        }
         let file = OpenOptions::new()
             .append(true)
             .create(true)
@@ -62,26 +66,27 @@ impl Logger {
     }
 
     pub fn info(&self, prefix: &'static str, msg: &str) {
-        self.logp(LogLevel::INFO, prefix, &msg);
+        self.logp(LogLevel::INFO, prefix, msg);
     }
 
+    /// Warnings go to stderr, so they never end up in output meant for other programs (ie `export` to stdout)
     pub fn warn(&self, prefix: &'static str, msg: &str) {
-        self.logp(LogLevel::WARN, prefix, &msg);
-        println!("{}", style(msg).yellow());
+        self.logp(LogLevel::WARN, prefix, msg);
+        eprintln!("{}", style(msg).yellow());
     }
 
     pub fn success(&self, prefix: &'static str, msg: &str) {
-        self.logp(LogLevel::WARN, prefix, &msg);
+        self.logp(LogLevel::WARN, prefix, msg);
         println!("{}", style(msg).green());
     }
 
     pub fn error(&self, prefix: &'static str, msg: &str) {
-        self.logp(LogLevel::ERROR, prefix, &msg);
+        self.logp(LogLevel::ERROR, prefix, msg);
         eprintln!("{} {}", style("Error: ").red().bold(), style(msg).red());
     }
 
     pub fn debug(&self, prefix: &'static str, msg: &str) {
-        self.logp(LogLevel::DEBUG, prefix, &msg);
+        self.logp(LogLevel::DEBUG, prefix, msg);
         println!("{}", style(msg).magenta());
     }
 }
\ No newline at end of file
@@ -1,12 +1,25 @@
+mod addoninfo;
+mod addonlist;
+mod category;
+mod conflicts;
+mod extract;
+mod keyvalues;
+mod steam;
+mod vpk;
 mod menu_import;
 mod menu_update;
 mod menu_search;
 mod menu_manage;
+mod menu_settings;
+mod menu_verify;
+mod menu_profiles;
+mod manifest;
+mod menu_collections;
 mod util;
 mod meta;
 mod logger;
 
-use dialoguer::{theme::ColorfulTheme, Select, Input};
+use dialoguer::{theme::ColorfulTheme, Select};
 use console::style;
 use std::path::PathBuf;
 use clap::{AppSettings, Clap};
@@ -22,27 +35,294 @@ struct Opts {
     menu: Option<String>,
     // #[clap(short, long, parse(from_occurrences))]
     // verbose: i32,
+    /// How many addons to download at once, overriding the saved setting
+    #[clap(long, parse(try_from_str = parse_concurrency))]
+    concurrency: Option<usize>,
+    /// Limit the combined download speed, ie 500K or 2M per second. Use 0 for unlimited
+    #[clap(long, parse(try_from_str = parse_limit_rate))]
+    limit_rate: Option<u64>,
+    /// Use a different config file. Can also be set with L4D2_DOWNLOADER_CONFIG
+    #[clap(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Wait for another running instance to finish instead of exiting
+    #[clap(long)]
+    wait: bool,
+    #[clap(subcommand)]
+    subcmd: Option<SubCommand>,
+}
+
+fn parse_concurrency(input: &str) -> Result<usize, String> {
+    match input.parse::<usize>() {
+        Ok(value) if (1..=meta::MAX_CONCURRENCY).contains(&value) => Ok(value),
+        _ => Err(format!("must be a number between 1 and {}", meta::MAX_CONCURRENCY))
+    }
+}
+
+fn parse_limit_rate(input: &str) -> Result<u64, String> {
+    util::parse_bytes(input).ok_or_else(|| "must be a number with an optional K, M or G suffix".to_string())
+}
+
+#[derive(Clap)]
+enum SubCommand {
+    /// Updates all outdated addons without opening the menu
+    Update(UpdateOpts),
+    /// Downloads workshop items or collections by their id or url
+    Download(DownloadOpts),
+    /// Checks all tracked addons for missing, corrupted or modified files
+    Verify(VerifyOpts),
+    /// Removes addons by their id or url
+    Remove(RemoveOpts),
+    /// Enables or disables addons in the game's addon list
+    SetEnabled(SetEnabledOpts),
+    /// Creates, lists, compares and activates addon profiles
+    Profile(ProfileOpts),
+    /// Writes all tracked addons and their versions to a manifest
+    Export(ExportOpts),
+    /// Downloads the addons in a manifest that are missing or at a different version
+    ImportManifest(ImportManifestOpts),
+    /// Follows a workshop collection so sync keeps its addons installed
+    Follow(FollowOpts),
+    /// Stops following a workshop collection
+    Unfollow(FollowOpts),
+    /// Downloads new and updated addons from followed collections
+    Sync(SyncOpts),
+    /// Lists files that more than one enabled addon replaces
+    Conflicts(ConflictsOpts),
+    /// Extracts files from an addon's VPK
+    Extract(ExtractOpts),
+}
+
+#[derive(Clap)]
+struct ExtractOpts {
+    /// Workshop id or url of a tracked addon, or the path of a VPK
+    addon: String,
+    /// Only extract files matching this glob, ie "materials/**" or "*.mdl"
+    pattern: Option<String>,
+    /// Folder to extract the files into
+    #[clap(short, long, parse(from_os_str))]
+    out: PathBuf,
+}
+
+#[derive(Clap)]
+struct ConflictsOpts {
+    /// Print every conflicting file as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Clap)]
+struct FollowOpts {
+    /// Workshop id or url of the collection
+    collection: String,
+}
+
+#[derive(Clap)]
+struct SyncOpts {
+    /// Remove addons that were dropped from a collection
+    #[clap(short, long)]
+    remove_dropped: bool,
+    /// Move dropped addons to the trash folder instead of deleting them
+    #[clap(short, long)]
+    trash: bool,
+    /// Do not ask for confirmation
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap)]
+struct ExportOpts {
+    /// Where to write the manifest. Use - for stdout
+    #[clap(default_value = "-")]
+    file: String,
+}
+
+#[derive(Clap)]
+struct ImportManifestOpts {
+    /// The manifest to import. Use - to read from stdin
+    file: String,
+    /// Fail instead of downloading newer versions when the workshop no longer matches the manifest
+    #[clap(long)]
+    frozen: bool,
+    /// Do not ask for confirmation before downloading
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap)]
+struct ProfileOpts {
+    #[clap(subcommand)]
+    subcmd: ProfileCommand,
+}
+
+#[derive(Clap)]
+enum ProfileCommand {
+    /// Creates a profile from the given addons, or from the currently loaded ones
+    Create(ProfileCreateOpts),
+    /// Lists all profiles
+    List,
+    /// Shows what activating a profile would change, or how two profiles differ
+    Diff(ProfileDiffOpts),
+    /// Loads exactly the addons in a profile
+    Activate(ProfileActivateOpts),
+}
+
+#[derive(Clap)]
+struct ProfileCreateOpts {
+    name: String,
+    /// Workshop ids or urls of tracked addons. Uses the currently loaded addons when empty
+    items: Vec<String>,
+}
+
+#[derive(Clap)]
+struct ProfileDiffOpts {
+    name: String,
+    /// Another profile to compare against
+    other: Option<String>,
+    /// Show the changes for parking instead of disabling addons
+    #[clap(short, long)]
+    park: bool,
+}
+
+#[derive(Clap)]
+struct ProfileActivateOpts {
+    name: String,
+    /// Move addons outside the profile to the parked folder instead of disabling them
+    #[clap(short, long)]
+    park: bool,
+}
+
+#[derive(Clap)]
+struct UpdateOpts {
+    /// Do not ask for confirmation before updating
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap)]
+struct VerifyOpts {
+    /// Re-download broken addons without asking
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap)]
+struct RemoveOpts {
+    /// Workshop ids or urls of the addons to remove
+    #[clap(required = true)]
+    items: Vec<String>,
+    /// Move the addons to the trash folder instead of deleting them
+    #[clap(short, long)]
+    trash: bool,
+    /// Do not ask for confirmation before removing
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap)]
+struct SetEnabledOpts {
+    /// Whether the addons should be enabled
+    #[clap(possible_values = &["on", "off"])]
+    state: String,
+    /// Workshop ids, urls or filenames of the addons
+    #[clap(required = true)]
+    items: Vec<String>,
+    /// Give every other addon the opposite state
+    #[clap(short, long)]
+    exclusive: bool,
+}
+
+#[derive(Clap)]
+struct DownloadOpts {
+    /// Workshop ids or urls to download
+    items: Vec<String>,
+    /// Read ids or urls from a file, one per line. Use - to read from stdin
+    #[clap(short, long)]
+    file: Option<String>,
 }
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts: Opts = Opts::parse();
-    println!("{} v{}", style("L4D2 Workshop Downloader").bold(), env!("CARGO_PKG_VERSION"));
+    //Subcommands keep stdout for their own output
+    let b_command = opts.subcmd.is_some();
+    let banner = format!("{} v{}", style("L4D2 Workshop Downloader").bold(), env!("CARGO_PKG_VERSION"));
+    if b_command { eprintln!("{}", banner) } else { println!("{}", banner) }
     //Grab the config or start initial setup
     let workshop = steam_workshop_api::Workshop::new(None);
-    let logger = logger::Logger::new(PathBuf::from(std::env::current_dir().unwrap()).join("downloader.log"));
+    let logger = logger::Logger::new(meta::get_log_path());
+    let config_path = meta::get_config_path(opts.config);
+    //Subcommands refuse to run alongside another instance unless asked to wait, the menu always waits
+    let b_wait = opts.wait || !b_command;
+    let _lock = match meta::ConfigLock::acquire(&config_path, false) {
+        Ok(Some(lock)) => lock,
+        Ok(None) if b_wait => {
+            eprintln!("Another instance is using {}, waiting for it to exit...", config_path.display());
+            match meta::ConfigLock::acquire(&config_path, true) {
+                Ok(Some(lock)) => lock,
+                Ok(None) => unreachable!(),
+                Err(err) => {
+                    eprintln!("{} {}", style("Could not lock the configuration:").red().bold(), err);
+                    std::process::exit(util::exit_code::ERROR);
+                }
+            }
+        },
+        Ok(None) => {
+            logger.log(LogLevel::ERROR, "Another instance is running, exiting");
+            eprintln!("{} {} is in use by another instance. Close it or pass --wait to wait for it.", style("Error:").red().bold(), config_path.display());
+            std::process::exit(util::exit_code::ERROR);
+        },
+        Err(err) => {
+            eprintln!("{} {}", style("Could not lock the configuration:").red().bold(), err);
+            std::process::exit(util::exit_code::ERROR);
+        }
+    };
+    match meta::migrate_config(&config_path) {
+        Ok(true) => {
+            let message = format!("Moved {} from the current directory to {}", meta::CONFIG_FILENAME, config_path.display());
+            logger.log(LogLevel::INFO, &message);
+            eprintln!("{}", message);
+        },
+        Ok(false) => {},
+        Err(err) => logger.warn("main/migrate_config", &format!("Could not move {} to {}: {}", meta::CONFIG_FILENAME, config_path.display(), err))
+    }
     //TODO: Add option to save file name 
+    let loaded = match meta::Config::load(&config_path) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            //Never fall through to the initial setup, it would overwrite the download history
+            logger.log(LogLevel::ERROR, &format!("Failed to load configuration {}: {}", config_path.display(), err));
+            eprintln!("{} {}", style("Failed to load configuration:").red().bold(), err);
+            if let meta::ConfigError::Parse(_) = err {
+                match meta::Config::backup_unreadable(&config_path) {
+                    Ok(backup) => eprintln!("A copy was saved to {}. Fix or remove {} and try again.", backup.display(), config_path.display()),
+                    Err(err) => eprintln!("Could not back up {}: {}", config_path.display(), err)
+                }
+            }
+            std::process::exit(util::exit_code::ERROR);
+        }
+    };
     let mut config = 
-        if let Some(config) = meta::Config::load() {
+        if let Some(config) = loaded {
             if !&config.gamedir.exists() {
                 eprintln!("Saved game directory does not exist: {}", &config.get_game_path_str().expect("< no path >"));
                 std::process::exit(1);
             }
-            println!("{} \"{}\"", style("Using saved directory:").bold(), &config.get_game_path_str().expect("< no path >"));
+            let message = format!("{} \"{}\"", style("Using saved directory:").bold(), &config.get_game_path_str().expect("< no path >"));
+            if b_command { eprintln!("{}", message) } else { println!("{}", message) }
             config
-        }else {
-            let path: PathBuf = prompt_for_path();
-            let config = meta::Config::new(path);
+        } else if opts.subcmd.is_some() {
+            eprintln!("No configuration was found at {}. Run without a subcommand first to complete the initial setup.", config_path.display());
+            std::process::exit(util::exit_code::ERROR);
+        } else {
+            println!("{}", style("Initial Setup").bold());
+            let path: PathBuf = match menu_settings::prompt_for_path() {
+                Some(path) => path,
+                None => {
+                    println!("A valid directory was not specified. Exiting.");
+                    std::process::exit(1);
+                }
+            };
+            let config = meta::Config::new(path, config_path);
             /*if let Some(prompt_res) = prompt_for_apikey() {
                 config.apikey = prompt_res.apikey;
             }*/
@@ -58,8 +338,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut params = util::MenuParams {
         config: &mut config,
         workshop: &workshop,
-        logger: &logger
+        logger: &logger,
+        overrides: util::DownloadOverrides {
+            concurrency: opts.concurrency,
+            bandwidth_limit: opts.limit_rate
+        }
     };
+
+    if let Some(subcmd) = opts.subcmd {
+        let code = match subcmd {
+            SubCommand::Update(update_opts) => menu_update::command(&mut params, update_opts.yes),
+            SubCommand::Download(download_opts) => {
+                let mut items = download_opts.items;
+                if let Some(file) = download_opts.file {
+                    match menu_search::read_inputs(&file) {
+                        Ok(lines) => items.extend(lines),
+                        Err(err) => {
+                            logger.error("CmdDownload/read_inputs", &format!("Could not read \"{}\": {}", file, err));
+                            std::process::exit(util::exit_code::ERROR);
+                        }
+                    }
+                }
+                menu_search::command(&mut params, &items)
+            },
+            SubCommand::Verify(verify_opts) => menu_verify::command(&mut params, verify_opts.yes),
+            SubCommand::Remove(remove_opts) => menu_manage::command(&mut params, &remove_opts.items, remove_opts.trash, remove_opts.yes),
+            SubCommand::SetEnabled(set_opts) => menu_manage::command_set_enabled(&mut params, &set_opts.items, set_opts.state == "on", set_opts.exclusive),
+            SubCommand::Profile(profile_opts) => match profile_opts.subcmd {
+                ProfileCommand::Create(create_opts) => menu_profiles::command_create(&mut params, &create_opts.name, &create_opts.items),
+                ProfileCommand::List => menu_profiles::command_list(&mut params),
+                ProfileCommand::Diff(diff_opts) => menu_profiles::command_diff(&mut params, &diff_opts.name, diff_opts.other.as_deref(), diff_opts.park),
+                ProfileCommand::Activate(activate_opts) => menu_profiles::command_activate(&mut params, &activate_opts.name, activate_opts.park)
+            },
+            SubCommand::Export(export_opts) => manifest::command_export(&mut params, &export_opts.file),
+            SubCommand::ImportManifest(import_opts) => manifest::command_import(&mut params, &import_opts.file, import_opts.frozen, import_opts.yes),
+            SubCommand::Follow(follow_opts) => menu_collections::command_follow(&mut params, &follow_opts.collection),
+            SubCommand::Unfollow(follow_opts) => menu_collections::command_unfollow(&mut params, &follow_opts.collection),
+            SubCommand::Sync(sync_opts) => menu_collections::command_sync(&mut params, sync_opts.remove_dropped, sync_opts.trash, sync_opts.yes),
+            SubCommand::Conflicts(conflicts_opts) => conflicts::command(&mut params, conflicts_opts.json),
+            SubCommand::Extract(extract_opts) => extract::command(&mut params, &extract_opts.addon, extract_opts.pattern.as_deref(), &extract_opts.out)
+        };
+        std::process::exit(code);
+    }
     //TODO: Add arg shortcut to this:
     if let Some(option) = opts.menu {
         let menu = match option.as_str() {
@@ -68,6 +388,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "import"   | "i" | "3" => 3,
             "update"   | "u" | "4" => 4,
             "settings" | "c" | "5" => 5,
+            "profiles" | "p" | "6" => 6,
+            "collections" | "f" | "7" => 7,
             _ => { println!("Unknown menu provided: \"{}\"", option); 0 }
         };
         if menu > 0 {
@@ -88,6 +410,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "3. Import Workshop Addons",
                 "4. Update Existing Addons",
                 "5. Change Settings",
+                "6. Addon Profiles",
+                "7. Followed Collections",
                 "Exit"
             ])
             .default(0)
@@ -106,6 +430,9 @@ fn open_menu(params: &mut util::MenuParams, number: usize) {
         1 => menu_search::handler(params),
         2 => menu_import::handler(params),
         3 => menu_update::handler(params),
+        4 => menu_settings::handler(params),
+        5 => menu_profiles::handler(params),
+        6 => menu_collections::handler(params),
         _ => std::process::exit(0)
     };
     match result {
@@ -117,63 +444,3 @@ fn open_menu(params: &mut util::MenuParams, number: usize) {
         }
     }
 }
-
-fn prompt_for_path() -> PathBuf {
-    match Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Initial Setup - Set L4D2 Addons Folder")
-        .items(&[
-            "Use Current Directory",
-            "Choose a directory",
-            "Input a path manually"
-        ])
-        .default(0)
-        .interact()
-        .unwrap()
-    {
-        0 => std::env::current_dir().unwrap(),
-        1 => {
-            match tinyfiledialogs::open_file_dialog(
-                "Choose where Left 4 Dead 2 is installed", 
-                "",
-                Some((&["left4dead2.exe"], "left4dead2.exe"))
-            ) {
-                Some(file_path) => {
-                    let path = PathBuf::from(file_path)
-                    .parent()
-                    .unwrap()
-                    .join("left4dead2")
-                    .join("addons");
-                    if !path.exists() {
-                        println!("A valid directory was not specified. Exiting.");
-                        std::process::exit(1);
-                    }
-                    path
-                },
-                _ => {
-                    println!("A valid directory was not specified. Exiting.");
-                    std::process::exit(1);
-                }    
-            }
-        },
-        2 => {
-            match Input::<String>::with_theme(&ColorfulTheme::default())
-                .with_prompt("Enter a path")
-                .interact_on(&console::Term::stdout())
-            {
-                Ok(path) => {
-                    let path = PathBuf::from(path);
-                    if !path.exists() {
-                        println!("A valid directory was not specified. Exiting.");
-                        std::process::exit(1);
-                    }
-                    path
-                },
-                Err(e) => {
-                    eprintln!("An error occurred: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        },
-        _ => panic!("Item is not valid")
-    }
-}
\ No newline at end of file
@@ -0,0 +1,180 @@
+use crate::util;
+use crate::meta::DownloadEntry;
+use crate::logger::LogLevel;
+
+use serde::{Deserialize, Serialize};
+use console::style;
+use std::{collections::HashMap, fs, io::{self, Read}};
+use steam_workshop_api::WorkshopItem;
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A standalone list of addons and the versions they are locked to, so other installs can download the same set
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub addons: Vec<DownloadEntry>
+}
+
+/// Writes every tracked addon to a manifest, returning one of the util::exit_code values. Use - for stdout
+pub fn command_export(menu: &mut util::MenuParams, path: &str) -> i32 {
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        addons: menu.config.downloads.clone()
+    };
+    let unhashed = manifest.addons.iter().filter(|entry| entry.sha256.is_none()).count();
+    if unhashed > 0 {
+        menu.logger.warn("CmdExport", &format!("{} addons have no recorded hash, run verify first to lock them to their current file", unhashed));
+    }
+    let json = match serde_json::to_string_pretty(&manifest) {
+        Ok(json) => json,
+        Err(err) => {
+            menu.logger.error("CmdExport/serialize", &err.to_string());
+            return util::exit_code::ERROR
+        }
+    };
+
+    if path == "-" {
+        println!("{}", json);
+    } else {
+        if let Err(err) = fs::write(path, json + "\n") {
+            menu.logger.error("CmdExport/write", &format!("Could not write \"{}\": {}", path, err));
+            return util::exit_code::ERROR
+        }
+        eprintln!("Exported {} addons to {}", manifest.addons.len(), path);
+    }
+    menu.logger.logp(LogLevel::INFO, "CmdExport", &format!("Exported {} addons to {}", manifest.addons.len(), path));
+    util::exit_code::UPDATED
+}
+
+/// Downloads every addon in a manifest that is missing or at a different version, returning one of the util::exit_code values.
+/// When `frozen` is set, nothing is downloaded unless the workshop still has exactly the locked versions
+pub fn command_import(menu: &mut util::MenuParams, path: &str, frozen: bool, yes: bool) -> i32 {
+    let manifest = match read_manifest(path) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            menu.logger.error("CmdImportManifest/read", &format!("Could not read manifest \"{}\": {}", path, err));
+            return util::exit_code::ERROR
+        }
+    };
+    if manifest.version > MANIFEST_VERSION {
+        menu.logger.error("CmdImportManifest", &format!("Manifest version {} is newer than the supported version {}", manifest.version, MANIFEST_VERSION));
+        return util::exit_code::ERROR
+    }
+
+    //Only addons that are not already at the locked version need to be fetched
+    let needed: Vec<&DownloadEntry> = manifest.addons.iter()
+        .filter(|locked| match menu.config.get_download(&locked.publishedfileid) {
+            Some(entry) => entry.time_updated != locked.time_updated || !menu.config.get_addon_path(&entry.publishedfileid).exists(),
+            None => true
+        })
+        .collect();
+    if needed.is_empty() {
+        println!("All {} addons in the manifest are installed.", manifest.addons.len());
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    let fileids: Vec<String> = needed.iter().map(|entry| entry.publishedfileid.clone()).collect();
+    let spinner = util::setup_spinner("Fetching Latest File Info...");
+    let details = match util::get_file_details(&fileids) {
+        Ok(details) => details,
+        Err(err) => {
+            spinner.abandon();
+            menu.logger.error("CmdImportManifest/get_file_details", &err.to_string());
+            return util::exit_code::ERROR
+        }
+    };
+    spinner.finish_and_clear();
+
+    let mut items: Vec<WorkshopItem> = Vec::with_capacity(needed.len());
+    let mut mismatched: usize = 0;
+    for locked in &needed {
+        match details.get(&locked.publishedfileid) {
+            Some(item) if item.time_updated == locked.time_updated => items.push(item.clone()),
+            Some(item) => {
+                mismatched += 1;
+                if frozen {
+                    menu.logger.error("CmdImportManifest", &format!("{} ({}) was updated on the workshop and no longer matches the manifest", locked.title, locked.publishedfileid));
+                } else {
+                    menu.logger.warn("CmdImportManifest", &format!("{} ({}) was updated on the workshop, downloading the latest version instead", locked.title, locked.publishedfileid));
+                    items.push(item.clone());
+                }
+            },
+            None => {
+                mismatched += 1;
+                menu.logger.error("CmdImportManifest", &format!("{} ({}) was removed from the workshop and cannot be downloaded", locked.title, locked.publishedfileid));
+            }
+        }
+    }
+    if frozen && mismatched > 0 {
+        eprintln!("{}", style(format!("{} addons no longer match the manifest, nothing was downloaded.", mismatched)).red());
+        return util::exit_code::ERROR
+    }
+    if items.is_empty() {
+        let missing: Vec<&str> = needed.iter().map(|entry| entry.publishedfileid.as_str()).collect();
+        menu.logger.error("CmdImportManifest", &format!("None of the addons could be downloaded, these are not on the workshop: {}", missing.join(", ")));
+        return util::exit_code::ERROR
+    }
+
+    if !util::confirm_command(&format!("Download {} addons from the manifest?", items.len()), yes) {
+        println!("Import was cancelled.");
+        return util::exit_code::NOT_CONFIRMED
+    }
+    //In frozen mode each file has to match its locked hash before it replaces the installed file
+    let hashes: HashMap<String, String> = if frozen {
+        needed.iter()
+            .filter_map(|entry| Some((entry.publishedfileid.clone(), entry.sha256.clone()?)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let report = match util::download_locked_addons(menu, &items, &hashes) {
+        Ok(report) => report,
+        Err(err) => {
+            menu.logger.error("CmdImportManifest/download_addons", &err.to_string());
+            return util::exit_code::ERROR
+        }
+    };
+
+    //The workshop can replace a file without changing its time_updated, so check the locked hashes too
+    let mut b_hash_mismatch = report.failed.iter().any(|(_, err)| matches!(err, util::DownloadError::HashMismatch { .. }));
+    if !frozen {
+        for fileid in &report.succeeded {
+            let locked = match needed.iter().find(|entry| &entry.publishedfileid == fileid) {
+                Some(locked) => locked,
+                None => continue
+            };
+            let current = match menu.config.get_download(fileid) {
+                //Newer versions were already reported above and are expected to have a different hash
+                Some(current) if current.time_updated == locked.time_updated => current,
+                _ => continue
+            };
+            if let (Some(locked_hash), Some(current_hash)) = (&locked.sha256, &current.sha256) {
+                if locked_hash != current_hash {
+                    b_hash_mismatch = true;
+                    menu.logger.warn("CmdImportManifest", &format!("{} ({}) does not match the hash in the manifest", locked.title, fileid));
+                }
+            }
+        }
+    }
+
+    menu.logger.logp(LogLevel::INFO, "CmdImportManifest", &format!("Downloaded {} of {} addons from {}", report.succeeded.len(), items.len(), path));
+    if frozen && b_hash_mismatch {
+        util::exit_code::ERROR
+    } else if (mismatched > 0 || b_hash_mismatch) && report.exit_code() == util::exit_code::UPDATED {
+        util::exit_code::PARTIAL_FAILURE
+    } else {
+        report.exit_code()
+    }
+}
+
+fn read_manifest(path: &str) -> Result<Manifest, Box<dyn std::error::Error>> {
+    let contents = if path == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else {
+        fs::read_to_string(path)?
+    };
+    Ok(serde_json::from_str(&contents)?)
+}
@@ -0,0 +1,478 @@
+use crate::util;
+use crate::meta::{Config, FollowedCollection};
+use crate::logger::LogLevel;
+
+use dialoguer::{theme::ColorfulTheme, Select, MultiSelect, Input, Confirm};
+use prettytable::{Table, Row, Cell, row};
+use console::style;
+use steam_workshop_api::WorkshopItem;
+
+/// The changes needed to bring the addons folder in line with a followed collection
+struct SyncPlan {
+    collection_id: String,
+    /// Every child currently in the collection
+    children: Vec<String>,
+    /// Children that are not tracked yet
+    added: Vec<WorkshopItem>,
+    /// Tracked children that have a newer version
+    changed: Vec<WorkshopItem>,
+    /// Tracked addons that were dropped from the collection since the last sync
+    dropped: Vec<String>,
+    /// Children that were removed from the workshop or are private
+    unavailable: Vec<String>
+}
+
+impl SyncPlan {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.dropped.is_empty()
+    }
+}
+
+pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
+    if menu.config.collections.is_empty() {
+        println!("No collections are being followed.");
+    } else {
+        print_collections(menu);
+    }
+    println!();
+
+    let choice = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Choose an option")
+        .items(&[
+            "Sync followed collections",
+            "Follow a collection",
+            "Unfollow a collection",
+            "Return"
+        ])
+        .default(0)
+        .interact()?;
+
+    match choice {
+        0 => {
+            for i in 0..menu.config.collections.len() {
+                let plan = match get_sync_plan(menu, i) {
+                    Some(plan) => plan,
+                    None => continue
+                };
+                prompt_sync(menu, plan)?;
+            }
+        },
+        1 => {
+            let input: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter a collection id or url")
+                .interact()?;
+            match util::Regexes::get_id_from_input(&input) {
+                Some(id) => { follow(menu, &id); },
+                None => println!("\"{}\" is not a valid id or url.", input)
+            }
+        },
+        2 => {
+            if menu.config.collections.is_empty() {
+                return Ok(None)
+            }
+            let titles: Vec<&String> = menu.config.collections.iter().map(|collection| &collection.title).collect();
+            let index = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select a collection to unfollow. Its addons are kept")
+                .items(&titles)
+                .default(0)
+                .interact()?;
+            let collection = menu.config.collections.remove(index);
+            menu.config.save()?;
+            println!("Unfollowed {}.", collection.title);
+            menu.logger.logp(LogLevel::INFO, "MenuCollections", &format!("Unfollowed collection {} ({})", collection.title, collection.publishedfileid));
+        },
+        _ => {}
+    }
+    Ok(None)
+}
+
+/// Starts following a collection, returning false if it could not be found. The next sync downloads its addons
+pub fn follow(menu: &mut util::MenuParams, id: &str) -> bool {
+    if menu.config.collections.iter().any(|collection| collection.publishedfileid == id) {
+        println!("Collection {} is already being followed.", id);
+        return true
+    }
+    let spinner = util::setup_spinner(format!("Fetching collection {}...", id));
+    let title = match (menu.workshop.get_collection_details(id), util::get_file_details(&[id.to_string()])) {
+        (Ok(Some(children)), Ok(details)) if !children.is_empty() => {
+            details.get(id).map(|item| item.title.clone()).unwrap_or_else(|| id.to_string())
+        },
+        (Err(err), _) => {
+            spinner.abandon();
+            menu.logger.error("MenuCollections/get_collection_details", &err.to_string());
+            return false
+        },
+        (_, Err(err)) => {
+            spinner.abandon();
+            menu.logger.error("MenuCollections/get_file_details", &err.to_string());
+            return false
+        },
+        _ => {
+            spinner.abandon();
+            menu.logger.error("MenuCollections/follow", &format!("{} is not a collection, or it is empty", id));
+            return false
+        }
+    };
+    spinner.finish_and_clear();
+
+    menu.config.collections.push(FollowedCollection {
+        publishedfileid: id.to_string(),
+        title: title.clone(),
+        children: Vec::new()
+    });
+    if let Err(err) = menu.config.save() {
+        menu.logger.error("MenuCollections/save", &err.to_string());
+        return false
+    }
+    println!("Now following {}. Sync to download its addons.", style(&title).bold());
+    menu.logger.logp(LogLevel::INFO, "MenuCollections", &format!("Followed collection {} ({})", title, id));
+    true
+}
+
+/// Follows a collection without the menu, returning one of the util::exit_code values
+pub fn command_follow(menu: &mut util::MenuParams, input: &str) -> i32 {
+    match util::Regexes::get_id_from_input(input) {
+        Some(id) if follow(menu, &id) => util::exit_code::UPDATED,
+        Some(_) => util::exit_code::ERROR,
+        None => {
+            menu.logger.error("CmdFollow", &format!("\"{}\" is not a valid id or url", input));
+            util::exit_code::ERROR
+        }
+    }
+}
+
+/// Stops following a collection without the menu, returning one of the util::exit_code values
+pub fn command_unfollow(menu: &mut util::MenuParams, input: &str) -> i32 {
+    let id = util::Regexes::get_id_from_input(input).unwrap_or_else(|| input.to_string());
+    let index = match menu.config.collections.iter().position(|collection| collection.publishedfileid == id) {
+        Some(index) => index,
+        None => {
+            menu.logger.error("CmdUnfollow", &format!("Collection {} is not being followed", id));
+            return util::exit_code::ERROR
+        }
+    };
+    let collection = menu.config.collections.remove(index);
+    if let Err(err) = menu.config.save() {
+        menu.logger.error("CmdUnfollow/save", &err.to_string());
+        return util::exit_code::ERROR
+    }
+    println!("Unfollowed {}.", collection.title);
+    menu.logger.logp(LogLevel::INFO, "CmdUnfollow", &format!("Unfollowed collection {} ({})", collection.title, collection.publishedfileid));
+    util::exit_code::UPDATED
+}
+
+/// Syncs every followed collection without the menu, returning one of the util::exit_code values.
+/// Addons dropped from a collection are only removed when `remove_dropped` is set
+pub fn command_sync(menu: &mut util::MenuParams, remove_dropped: bool, trash: bool, yes: bool) -> i32 {
+    if menu.config.collections.is_empty() {
+        println!("No collections are being followed.");
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    let mut codes: Vec<i32> = Vec::with_capacity(menu.config.collections.len());
+    for i in 0..menu.config.collections.len() {
+        let plan = match get_sync_plan(menu, i) {
+            Some(plan) => plan,
+            None => {
+                codes.push(util::exit_code::ERROR);
+                continue
+            }
+        };
+        codes.push(apply_sync_plan(menu, &plan, remove_dropped, trash, yes));
+    }
+
+    //Report the worst outcome across all collections
+    if codes.iter().all(|code| *code == util::exit_code::NOTHING_TO_DO) {
+        util::exit_code::NOTHING_TO_DO
+    } else if codes.iter().all(|code| *code == util::exit_code::ERROR) {
+        util::exit_code::ERROR
+    } else if codes.iter().any(|code| *code == util::exit_code::ERROR || *code == util::exit_code::PARTIAL_FAILURE) {
+        util::exit_code::PARTIAL_FAILURE
+    } else if codes.contains(&util::exit_code::NOT_CONFIRMED) {
+        util::exit_code::NOT_CONFIRMED
+    } else {
+        util::exit_code::UPDATED
+    }
+}
+
+/// Downloads the new and updated addons of a plan, and removes the dropped ones when `remove_dropped` is set.
+/// Returns one of the util::exit_code values
+fn apply_sync_plan(menu: &mut util::MenuParams, plan: &SyncPlan, remove_dropped: bool, trash: bool, yes: bool) -> i32 {
+    if plan.is_empty() {
+        save_children(menu, plan, &[]);
+        return if plan.unavailable.is_empty() { util::exit_code::NOTHING_TO_DO } else { util::exit_code::PARTIAL_FAILURE }
+    }
+
+    let mut items = plan.added.clone();
+    items.extend(plan.changed.iter().cloned());
+    let mut code = util::exit_code::NOTHING_TO_DO;
+    if !items.is_empty() {
+        if !util::confirm_command(&format!("Download {} addons?", items.len()), yes) {
+            println!("Sync was cancelled.");
+            return util::exit_code::NOT_CONFIRMED
+        }
+        code = match util::download_addons(menu, &items) {
+            Ok(report) => report.exit_code(),
+            Err(err) => {
+                menu.logger.error("CmdSync/download_addons", &err.to_string());
+                util::exit_code::ERROR
+            }
+        };
+    }
+
+    //Dropped addons that are kept stay in the stored children, so a later sync can still remove them
+    let mut kept: Vec<String> = Vec::new();
+    if !plan.dropped.is_empty() {
+        if !remove_dropped {
+            println!("{} addons were dropped from the collection and were kept. Use --remove-dropped to remove them.", plan.dropped.len());
+            kept = plan.dropped.clone();
+        } else if util::confirm_command(&format!("Remove {} addons that were dropped from the collection?", plan.dropped.len()), yes) {
+            for id in &plan.dropped {
+                let title = get_title(menu, id);
+                let filename = menu.config.get_addon_filename(id);
+                if !crate::menu_manage::remove_addon(menu, Some(id), &filename, &title, trash) {
+                    code = util::exit_code::PARTIAL_FAILURE;
+                    kept.push(id.clone());
+                }
+            }
+            if code == util::exit_code::NOTHING_TO_DO {
+                code = util::exit_code::UPDATED;
+            }
+        } else {
+            println!("Removal was not confirmed, the {} addons dropped from the collection were kept.", plan.dropped.len());
+            kept = plan.dropped.clone();
+            if code == util::exit_code::NOTHING_TO_DO || code == util::exit_code::UPDATED {
+                code = util::exit_code::NOT_CONFIRMED;
+            }
+        }
+    }
+    if code != util::exit_code::ERROR {
+        save_children(menu, plan, &kept);
+    }
+    if !plan.unavailable.is_empty() && code == util::exit_code::UPDATED {
+        code = util::exit_code::PARTIAL_FAILURE;
+    }
+    code
+}
+
+fn get_title(menu: &util::MenuParams, id: &str) -> String {
+    match menu.config.get_download(id) {
+        Some(entry) => entry.title.clone(),
+        None => id.to_string()
+    }
+}
+
+/// Compares a followed collection with the tracked addons and prints the differences.
+/// Returns None if the collection could not be fetched
+fn get_sync_plan(menu: &util::MenuParams, index: usize) -> Option<SyncPlan> {
+    let collection = &menu.config.collections[index];
+    println!("{}", style(format!("Collection: {}", collection.title)).bold());
+    let spinner = util::setup_spinner("Fetching collection children...");
+    let children = match menu.workshop.get_collection_details(&collection.publishedfileid) {
+        Ok(Some(children)) => children,
+        Ok(None) => {
+            spinner.abandon();
+            menu.logger.error("MenuCollections/sync", &format!("Collection {} no longer exists", collection.publishedfileid));
+            return None
+        },
+        Err(err) => {
+            spinner.abandon();
+            menu.logger.error("MenuCollections/get_collection_details", &err.to_string());
+            return None
+        }
+    };
+    let details = match util::get_file_details(&children) {
+        Ok(details) => details,
+        Err(err) => {
+            spinner.abandon();
+            menu.logger.error("MenuCollections/get_file_details", &err.to_string());
+            return None
+        }
+    };
+    spinner.finish_and_clear();
+
+    let mut added: Vec<WorkshopItem> = Vec::new();
+    let mut changed: Vec<WorkshopItem> = Vec::new();
+    for item in &details.items {
+        match menu.config.get_download(&item.publishedfileid) {
+            None => added.push(item.clone()),
+            Some(entry) if item.time_updated > entry.time_updated => changed.push(item.clone()),
+            Some(_) => {}
+        }
+    }
+    let dropped = get_dropped(menu.config, index, &children);
+
+    for item in &added {
+        println!("{} {} (new)", style("+").green(), item.title);
+    }
+    for item in &changed {
+        println!("{} {} (updated)", style("~").cyan(), item.title);
+    }
+    for id in &dropped {
+        println!("{} {} (dropped from collection)", style("-").red(), get_title(menu, id));
+    }
+    for id in &details.missing {
+        println!("{} {} is unavailable and will be skipped", style("!").yellow(), id);
+    }
+
+    let plan = SyncPlan {
+        collection_id: collection.publishedfileid.clone(),
+        children,
+        added,
+        changed,
+        dropped,
+        unavailable: details.missing
+    };
+    if plan.is_empty() {
+        println!("All {} addons in the collection are up-to-date.", plan.children.len());
+    }
+    Some(plan)
+}
+
+/// Gets the tracked addons that were in a followed collection at the last sync, but are no longer in `children`.
+/// Addons still in another followed collection are not dropped
+fn get_dropped(config: &Config, index: usize, children: &[String]) -> Vec<String> {
+    config.collections[index].children.iter()
+        .filter(|id| !children.contains(id) && config.get_download(id).is_some())
+        .filter(|id| !config.collections.iter().enumerate().any(|(i, other)| i != index && other.children.contains(id)))
+        .cloned()
+        .collect()
+}
+
+fn prompt_sync(menu: &mut util::MenuParams, plan: SyncPlan) -> Result<(), Box<dyn std::error::Error>> {
+    let mut items = plan.added.clone();
+    items.extend(plan.changed.iter().cloned());
+    if !items.is_empty() {
+        println!();
+        if !Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Download {} addons?", items.len()))
+            .default(true)
+            .interact()?
+        {
+            println!("Sync was cancelled.");
+            return Ok(())
+        }
+        let report = util::download_addons(menu, &items)?;
+        println!("{}", style(format!("{} addons downloaded.", report.succeeded.len())).bold());
+    }
+
+    let mut kept: Vec<String> = Vec::new();
+    if !plan.dropped.is_empty() {
+        let labels: Vec<String> = plan.dropped.iter().map(|id| get_title(menu, id)).collect();
+        let selections = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt("These addons were dropped from the collection. Select any to remove (space to select, enter to confirm)")
+            .items(&labels)
+            .interact()?;
+        if !selections.is_empty() {
+            let trash = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("How should the {} addons be removed?", selections.len()))
+                .items(&[
+                    "Move to the trash folder",
+                    "Delete permanently"
+                ])
+                .default(0)
+                .interact()? == 0;
+            for i in &selections {
+                let filename = menu.config.get_addon_filename(&plan.dropped[*i]);
+                if !crate::menu_manage::remove_addon(menu, Some(&plan.dropped[*i]), &filename, &labels[*i], trash) {
+                    kept.push(plan.dropped[*i].clone());
+                }
+            }
+        }
+        kept.extend(plan.dropped.iter().enumerate().filter(|(i, _)| !selections.contains(i)).map(|(_, id)| id.clone()));
+    }
+    save_children(menu, &plan, &kept);
+    Ok(())
+}
+
+/// Remembers the collection's children, so addons dropped later can be found.
+/// `kept` are dropped addons that were not removed, which are remembered too so a later sync offers them again
+fn save_children(menu: &mut util::MenuParams, plan: &SyncPlan, kept: &[String]) {
+    if let Some(collection) = menu.config.collections.iter_mut().find(|collection| collection.publishedfileid == plan.collection_id) {
+        collection.children = plan.children.iter().chain(kept).cloned().collect();
+    }
+    if let Err(err) = menu.config.save() {
+        menu.logger.error("MenuCollections/save", &format!("Could not save the collection: {}", err));
+    }
+}
+
+fn print_collections(menu: &util::MenuParams) {
+    let mut table = Table::new();
+    table.set_titles(row!["Collection", "ID", "Addons"]);
+    for collection in &menu.config.collections {
+        table.add_row(Row::new(vec![
+            Cell::new(&collection.title),
+            Cell::new(&collection.publishedfileid),
+            Cell::new(&collection.children.len().to_string())
+        ]));
+    }
+    table.printstd();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::Logger;
+    use crate::meta::DownloadEntry;
+    use std::fs;
+
+    fn get_entry(id: &str) -> DownloadEntry {
+        DownloadEntry {
+            title: format!("Addon {}", id),
+            publishedfileid: id.to_string(),
+            time_updated: 1,
+            sha256: None
+        }
+    }
+
+    fn get_plan(config: &Config, children: &[&str]) -> SyncPlan {
+        let children: Vec<String> = children.iter().map(|id| id.to_string()).collect();
+        SyncPlan {
+            collection_id: config.collections[0].publishedfileid.clone(),
+            dropped: get_dropped(config, 0, &children),
+            children,
+            added: Vec::new(),
+            changed: Vec::new(),
+            unavailable: Vec::new()
+        }
+    }
+
+    #[test]
+    fn kept_dropped_addons_can_be_removed_by_a_later_sync() {
+        let dir = std::env::temp_dir().join(format!("l4d2-sync-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let gamedir = dir.join("addons");
+        fs::create_dir_all(&gamedir).unwrap();
+        fs::write(gamedir.join("1111111.vpk"), b"kept").unwrap();
+        fs::write(gamedir.join("2222222.vpk"), b"dropped").unwrap();
+
+        let mut config = Config::new(gamedir.clone(), dir.join("config.json"));
+        config.add_download(get_entry("1111111"));
+        config.add_download(get_entry("2222222"));
+        config.collections.push(FollowedCollection {
+            publishedfileid: "9999999".to_string(),
+            title: "Collection".to_string(),
+            children: vec!["1111111".to_string(), "2222222".to_string()]
+        });
+        let workshop = steam_workshop_api::Workshop::new(None);
+        let logger = Logger::new(dir.join("test.log"));
+
+        //The addon was dropped, but is kept without --remove-dropped
+        let plan = get_plan(&config, &["1111111"]);
+        assert_eq!(plan.dropped, ["2222222"]);
+        let mut menu = util::MenuParams { config: &mut config, workshop: &workshop, logger: &logger, overrides: Default::default() };
+        assert_eq!(apply_sync_plan(&mut menu, &plan, false, false, true), util::exit_code::NOTHING_TO_DO);
+        assert!(gamedir.join("2222222.vpk").exists());
+        assert!(menu.config.collections[0].children.contains(&"2222222".to_string()));
+
+        //So a later sync with --remove-dropped still finds and removes it
+        let plan = get_plan(menu.config, &["1111111"]);
+        assert_eq!(plan.dropped, ["2222222"]);
+        assert_eq!(apply_sync_plan(&mut menu, &plan, true, false, true), util::exit_code::UPDATED);
+        assert!(!gamedir.join("2222222.vpk").exists());
+        assert!(gamedir.join("1111111.vpk").exists());
+        assert!(menu.config.get_download("2222222").is_none());
+        assert_eq!(menu.config.collections[0].children, ["1111111"]);
+        assert!(get_plan(menu.config, &["1111111"]).dropped.is_empty());
+    }
+}
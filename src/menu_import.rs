@@ -76,7 +76,8 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
             let download = DownloadEntry {
                 title: item.title.to_string(),
                 publishedfileid: item.publishedfileid.to_string(),
-                time_updated: item.time_updated
+                time_updated: item.time_updated,
+                sha256: None
             };
             selected_vpks.push(download);
         }
@@ -94,17 +95,16 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
         let src_folder = menu.config.gamedir.join("workshop");
         //Loop each selected item and move it down a directory (addons/workshop -> addons/)
         for download in selected_vpks {
-            let filename = format!("{}.vpk", &download.publishedfileid);
-            fs::rename(src_folder.join(&filename), dest_folder.join(&filename))?;
+            let src = src_folder.join(format!("{}.vpk", &download.publishedfileid));
+            let dest = dest_folder.join(format!("{}.vpk", menu.config.format_filename(&download.publishedfileid, &download.title)));
+            fs::rename(src, dest)?;
             menu.config.add_download(download);
         }
         match menu.config.save() {
             Ok(()) => { 
-                println!("{}\n{}\n{}",
-                    console::style(format!("Succesfully imported {} files", item_count)).bold(), 
-                    "Unsubscribe from the imported addons or they will be loaded twice the next time you start the game.",
-                    "https://steamcommunity.com/id/<your id>/myworkshopfiles/?appid=550&browsefilter=mysubscriptions and click the [Unsubscribe From All] button"
-                );
+                println!("{}", console::style(format!("Succesfully imported {} files", item_count)).bold());
+                println!("Unsubscribe from the imported addons or they will be loaded twice the next time you start the game.");
+                println!("https://steamcommunity.com/id/<your id>/myworkshopfiles/?appid=550&browsefilter=mysubscriptions and click the [Unsubscribe From All] button");
                 menu.logger.logp(LogLevel::SUCCESS, "MenuImport", &format!("Imported {} workshop items", item_count));
             },
             Err(err) => {
@@ -1,38 +1,68 @@
 use crate::util;
+use crate::logger::LogLevel;
+use crate::addonlist::AddonList;
+use crate::addoninfo::AddonInfo;
+use crate::category::{self, Category};
 
-use steam_workshop_api::{Workshop, WorkshopItem};
-use dialoguer::{theme::ColorfulTheme, Select};
-use prettytable::{Table, Row, Cell, row, cell};
+use steam_workshop_api::Workshop;
+use dialoguer::{theme::ColorfulTheme, Select, MultiSelect, Confirm};
+use prettytable::{Table, Row, Cell, row};
 use chrono::prelude::*;
+use rayon::prelude::*;
+use std::{collections::HashMap, path::Path};
+
+/// Folder inside the addons folder that removed addons are moved to, instead of being deleted
+const TRASH_FOLDER: &str = "trash";
+
+/// A vpk in the addons folder that can be removed
+struct AddonFile {
+    id: Option<String>,
+    /// Filename without the .vpk extension
+    filename: String,
+    title: String
+}
 
 struct UnknownFile {
     filename: String,
     size: Option<u64>,
-    modified: Option<std::time::SystemTime>
+    modified: Option<std::time::SystemTime>,
+    /// The VPK's addoninfo.txt, if it has one
+    info: Option<AddonInfo>
 }
 
 pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
     let mut unknownid_filenames: Vec<UnknownFile> = Vec::new();
+    let mut id_filenames: Vec<(String, String)> = Vec::new();
     let fileids = match Workshop::get_vpks_in_folder(&menu.config.gamedir) {
         Ok(results) => {
             //Tries to find an ID to parse
             let mut fileids: Vec<String> = Vec::with_capacity(results.len());
             for filename in results.iter() {
-                if let Some(id) = util::Regexes::get_filename_addonid(&filename) {
+                if let Some(id) = util::Regexes::get_filename_addonid(filename) {
+                    id_filenames.push((id.clone(), filename.clone()));
                     fileids.push(id);
                 } else {
-                    let full_file = format!("{}.vpk", filename);
-                    if let Ok(metadata) = std::fs::metadata(&menu.config.gamedir.join(full_file)) {
+                    let full_path = menu.config.gamedir.join(format!("{}.vpk", filename));
+                    let info = match AddonInfo::from_vpk(&full_path) {
+                        Ok(info) => info,
+                        Err(err) => {
+                            menu.logger.logp(LogLevel::WARN, "MenuManage/AddonInfo", &format!("Could not read {}.vpk: {}", filename, err));
+                            None
+                        }
+                    };
+                    if let Ok(metadata) = std::fs::metadata(&full_path) {
                         unknownid_filenames.push(UnknownFile {
                             filename: filename.clone(), 
                             size: Some(metadata.len()),
-                            modified: metadata.modified().ok()
+                            modified: metadata.modified().ok(),
+                            info
                         });
                     } else {
                         unknownid_filenames.push(UnknownFile {
                             filename: filename.clone(), 
                             size: None,
-                            modified: None
+                            modified: None,
+                            info
                         });
                     }
                 }
@@ -49,25 +79,73 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
     };
 
     let spinner = util::setup_spinner("Getting VPK Details...");
-    let details: Vec<WorkshopItem> = match menu.workshop.get_published_file_details(&fileids) {
-        Ok(details) => details,
-        Err(err) => { 
-            spinner.abandon();
-            menu.logger.error("MenuManage/get_file_details", &err.to_string());
-            return Ok(None)
+    let details = if fileids.is_empty() {
+        util::FileDetails { items: Vec::new(), missing: Vec::new() }
+    } else {
+        match util::get_file_details(&fileids) {
+            Ok(details) => details,
+            Err(err) => {
+                spinner.abandon();
+                menu.logger.error("MenuManage/get_file_details", &err.to_string());
+                return Ok(None)
+            }
         }
     };
 
     spinner.finish_and_clear();
 
-    println!("{}", console::style("Workshop Items").bold());
-    let mut table = Table::new();
-    table.set_titles(row!["Item Name", "File Size", "Last Update", "Status"]);
+    //Every vpk in the addons folder, labelled with its title when known
+    let mut addons: Vec<AddonFile> = Vec::with_capacity(id_filenames.len() + unknownid_filenames.len());
+    for (id, filename) in &id_filenames {
+        let title = match (details.get(id), menu.config.get_download(id)) {
+            (Some(item), _) => item.title.clone(),
+            (None, Some(downloaded)) => downloaded.title.clone(),
+            (None, None) => filename.clone()
+        };
+        addons.push(AddonFile { id: Some(id.clone()), filename: filename.clone(), title });
+    }
+    for unknown in &unknownid_filenames {
+        addons.push(AddonFile { id: None, title: get_unknown_title(unknown), filename: unknown.filename.clone() });
+    }
+
+    let addonlist = match AddonList::load(&menu.config.gamedir) {
+        Ok(addonlist) => Some(addonlist),
+        Err(err) => {
+            menu.logger.warn("MenuManage/AddonList", &format!("Could not read {}: {}", AddonList::get_path(&menu.config.gamedir).display(), err));
+            None
+        }
+    };
+    let get_enabled_cell = |filename: &str| -> Cell {
+        match &addonlist {
+            Some(addonlist) if addonlist.is_enabled(&format!("{}.vpk", filename)) => Cell::new("Yes"),
+            Some(_) => Cell::new("No"),
+            None => Cell::new("?")
+        }
+    };
+    let get_id_filename = |id: &str| -> String {
+        match id_filenames.iter().find(|(fileid, _)| fileid == id) {
+            Some((_, filename)) => filename.clone(),
+            None => id.to_string()
+        }
+    };
+
+    //Reading every VPK's directory tree is the slow part, so do it in parallel
+    let gamedir = &menu.config.gamedir;
+    let vpk_categories: HashMap<String, Option<Category>> = addons
+        .par_iter()
+        .map(|addon| (addon.filename.clone(), category::from_vpk(&gamedir.join(format!("{}.vpk", addon.filename)))))
+        .collect();
+    let get_category = |filename: &str, tags: &[String]| -> Category {
+        category::classify(category::from_tags(tags), vpk_categories.get(filename).copied().flatten())
+    };
+
+    let mut rows: Vec<(Category, Row)> = Vec::with_capacity(addons.len());
 
     let mut b_any_update_available = false;
     let mut b_external_files_exist = false;
+    let mut b_identified_files_exist = false;
 
-    for item in &details {
+    for item in &details.items {
         let mut date = chrono::Utc.timestamp_opt(item.time_updated as i64, 0);
         let status_cell = match menu.config.get_download(&item.publishedfileid) {
             Some(downloaded) => {
@@ -84,16 +162,34 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                 Cell::new("Unimported Addon")
             }
         };
-        table.add_row(
-            Row::new(vec![
-                Cell::new(&item.title),
-                Cell::new(&util::format_bytes(item.file_size)),
-                Cell::new(&date.unwrap().format("%Y/%m/%d").to_string()),
-                status_cell,
-            ])
-        );
+        let filename = get_id_filename(&item.publishedfileid);
+        let category = get_category(&filename, &util::get_item_tags(item));
+        rows.push((category, Row::new(vec![
+            Cell::new(&item.title),
+            Cell::new(&category.to_string()),
+            Cell::new(&util::format_bytes(item.file_size)),
+            Cell::new(&date.unwrap().format("%Y/%m/%d").to_string()),
+            status_cell,
+            get_enabled_cell(&filename),
+        ])));
     }
-    for unknown in unknownid_filenames {
+    for fileid in &details.missing {
+        let title = match menu.config.get_download(fileid) {
+            Some(downloaded) => downloaded.title.clone(),
+            None => fileid.clone()
+        };
+        let filename = get_id_filename(fileid);
+        let category = get_category(&filename, &[]);
+        rows.push((category, Row::new(vec![
+            Cell::new(&title),
+            Cell::new(&category.to_string()),
+            Cell::new("n/a"),
+            Cell::new("n/a"),
+            Cell::new("Removed from Workshop"),
+            get_enabled_cell(&filename),
+        ])));
+    }
+    for unknown in &unknownid_filenames {
         let size_cell_text: String = match unknown.size {
             Some(size) => util::format_bytes(size),
             None => "n/a".to_owned()
@@ -105,30 +201,52 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
             },
             None => "n/a".to_owned()
         };
-        table.add_row(
-            Row::new(vec![
-                Cell::new(&unknown.filename),
-                Cell::new(&size_cell_text),
-                Cell::new(&date_cell_text),
-                Cell::new("(No ID Found)"),
-            ])
-        );
+        let status_text = match unknown.info.as_ref().and_then(|info| info.workshop_id.as_ref()) {
+            Some(id) => {
+                b_identified_files_exist = true;
+                format!("Identified ({})", id)
+            },
+            None => "(No ID Found)".to_string()
+        };
+        let category = get_category(&unknown.filename, &[]);
+        rows.push((category, Row::new(vec![
+            Cell::new(&get_unknown_title(unknown)),
+            Cell::new(&category.to_string()),
+            Cell::new(&size_cell_text),
+            Cell::new(&date_cell_text),
+            Cell::new(&status_text),
+            get_enabled_cell(&unknown.filename),
+        ])));
     }
-    table.printstd();
+    print_rows(&rows, None, false);
+    print_addon_infos(&unknownid_filenames);
 
-    match Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select an option")
-        .items(&[
-            "Import external files",
-            "Update all addons"
-        ])
-        .interact()
-        .unwrap()
-    {
+    //Filtering only reprints the table, so keep asking until another option is chosen
+    let choice = loop {
+        let choice = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select an option")
+            .items(&[
+                "Import external files",
+                "Update all addons",
+                "Verify addon files",
+                "Remove addons",
+                "Enable / disable addons",
+                "Import identified addons",
+                "View file conflicts",
+                "Filter or sort by category"
+            ])
+            .interact()?;
+        if choice != 7 {
+            break choice
+        }
+        prompt_category_view(&rows)?;
+    };
+
+    match choice {
         0 => {
             if b_external_files_exist {
-                for item in details {
-                    if let None = menu.config.get_download(&item.publishedfileid) {
+                for item in details.items {
+                    if menu.config.get_download(&item.publishedfileid).is_none() {
                         menu.config.add_download(crate::meta::DownloadEntry::from_item(&item));
                     };
                 }
@@ -146,7 +264,464 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                 println!("There are no addons that have an update.");
             }
         },
+        2 => return crate::menu_verify::handler(menu),
+        3 => prompt_remove(menu, &addons)?,
+        4 => match addonlist {
+            Some(mut addonlist) => prompt_toggle(menu, &mut addonlist, &addons)?,
+            None => println!("The addon list could not be read, so addons cannot be enabled or disabled.")
+        },
+        5 => {
+            if b_identified_files_exist {
+                prompt_import_identified(menu, &unknownid_filenames, addonlist)?;
+            } else {
+                println!("There are no addons with a workshop id in their addoninfo.txt.");
+            }
+        },
+        6 => return crate::conflicts::handler(menu),
         choice => println!("choice {}", choice)
     }
     Ok(None)
 }
+
+/// Prints the addons table, only showing one category when `filter` is given
+fn print_rows(rows: &[(Category, Row)], filter: Option<Category>, sort: bool) {
+    let mut rows: Vec<&(Category, Row)> = rows.iter()
+        .filter(|(category, _)| filter.map(|filter| filter == *category).unwrap_or(true))
+        .collect();
+    if sort {
+        //Stable, so addons keep their order within a category
+        rows.sort_by_key(|(category, _)| *category);
+    }
+    println!("{}", console::style("Workshop Items").bold());
+    let mut table = Table::new();
+    table.set_titles(row!["Item Name", "Category", "File Size", "Last Update", "Status", "Enabled"]);
+    for (_, row) in rows {
+        table.add_row(row.clone());
+    }
+    table.printstd();
+}
+
+/// Asks how to filter or sort the addons table, then prints it again
+fn prompt_category_view(rows: &[(Category, Row)]) -> Result<(), Box<dyn std::error::Error>> {
+    //Only offer the categories that some addon has
+    let present: Vec<Category> = category::CATEGORIES.iter()
+        .copied()
+        .filter(|category| rows.iter().any(|(row_category, _)| row_category == category))
+        .collect();
+    let mut items: Vec<String> = vec!["Show all, sorted by category".to_string()];
+    for category in &present {
+        let count = rows.iter().filter(|(row_category, _)| row_category == category).count();
+        items.push(format!("Only {} ({})", category, count));
+    }
+    let choice = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Show which addons?")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    println!();
+    match choice {
+        0 => print_rows(rows, None, true),
+        i => print_rows(rows, Some(present[i - 1]), false)
+    }
+    Ok(())
+}
+
+/// Gets the name to show for a vpk without an id in its filename
+fn get_unknown_title(unknown: &UnknownFile) -> String {
+    match unknown.info.as_ref().and_then(|info| info.title.as_ref()) {
+        Some(title) => format!("{} ({}.vpk)", title, unknown.filename),
+        None => unknown.filename.clone()
+    }
+}
+
+/// Checks if an addon's own title and its workshop title share a word, ignoring case and punctuation
+fn is_title_match(title: &str, workshop_title: &str) -> bool {
+    let get_words = |title: &str| -> Vec<String> {
+        title.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.len() > 2)
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let words = get_words(title);
+    let workshop_words = get_words(workshop_title);
+    words.is_empty() || workshop_words.is_empty() || words.iter().any(|word| workshop_words.contains(word))
+}
+
+/// Prints what the addoninfo.txt of each unknown vpk says about it
+fn print_addon_infos(unknowns: &[UnknownFile]) {
+    let mut table = Table::new();
+    table.set_titles(row!["File", "Title", "Author", "Version", "Content", "Workshop ID"]);
+    for unknown in unknowns {
+        let info = match &unknown.info {
+            Some(info) => info,
+            None => continue
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&format!("{}.vpk", unknown.filename)),
+            Cell::new(info.title.as_deref().unwrap_or("")),
+            Cell::new(info.author.as_deref().unwrap_or("")),
+            Cell::new(info.version.as_deref().unwrap_or("")),
+            Cell::new(&info.content.join(", ")),
+            Cell::new(info.workshop_id.as_deref().unwrap_or(""))
+        ]));
+    }
+    if !table.is_empty() {
+        println!("{}", console::style("Addon Info of Unknown Files").bold());
+        table.printstd();
+    }
+}
+
+/// Imports unknown vpks whose addoninfo.txt has a workshop id, renaming them to the name downloads get so they can be updated
+fn prompt_import_identified(menu: &mut util::MenuParams, unknowns: &[UnknownFile], mut addonlist: Option<AddonList>) -> Result<(), Box<dyn std::error::Error>> {
+    let identified: Vec<(&UnknownFile, &String)> = unknowns.iter()
+        .filter_map(|unknown| Some((unknown, unknown.info.as_ref()?.workshop_id.as_ref()?)))
+        .filter(|(_, id)| menu.config.get_download(id).is_none())
+        .collect();
+    if identified.is_empty() {
+        println!("All identified addons are already imported.");
+        return Ok(())
+    }
+
+    let ids: Vec<String> = identified.iter().map(|(_, id)| (*id).clone()).collect();
+    let spinner = util::setup_spinner("Getting VPK Details...");
+    let details = match util::get_file_details(&ids) {
+        Ok(details) => details,
+        Err(err) => {
+            spinner.abandon();
+            menu.logger.error("MenuManage/ImportIdentified", &err.to_string());
+            return Ok(())
+        }
+    };
+    spinner.finish_and_clear();
+
+    //Show the workshop's title next to the addon's own, so a wrong id in addoninfo.txt is easy to spot
+    let mut labels: Vec<String> = Vec::with_capacity(identified.len());
+    let mut defaults: Vec<bool> = Vec::with_capacity(identified.len());
+    for (unknown, id) in &identified {
+        let item = match details.get(id) {
+            Some(item) => item,
+            None => {
+                labels.push(format!("{} - {} is not on the workshop", get_unknown_title(unknown), id));
+                defaults.push(false);
+                continue
+            }
+        };
+        let title = unknown.info.as_ref().and_then(|info| info.title.as_deref());
+        if title.map(|title| is_title_match(title, &item.title)).unwrap_or(true) {
+            labels.push(format!("{} - {} ({})", get_unknown_title(unknown), item.title, id));
+            defaults.push(true);
+        } else {
+            labels.push(format!("{} - {} ({}) {}", get_unknown_title(unknown), item.title, id, console::style("titles differ").yellow()));
+            defaults.push(false);
+        }
+    }
+    if defaults.iter().any(|selected| !selected) {
+        println!("{}", console::style("Addons whose workshop item does not match their title are not selected, check them before importing.").yellow());
+    }
+    let selections = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select addons to import (space to select, enter to confirm)")
+        .items(&labels)
+        .defaults(&defaults)
+        .interact()?;
+    if selections.is_empty() {
+        println!("No addons were selected.");
+        return Ok(())
+    }
+
+    let mut imported: usize = 0;
+    let mut b_addonlist_changed = false;
+    for i in selections {
+        let (unknown, id) = identified[i];
+        let item = match details.get(id) {
+            Some(item) => item,
+            None => {
+                menu.logger.warn("MenuManage/ImportIdentified", &format!("{} is not on the workshop, so {}.vpk was not imported", id, unknown.filename));
+                continue
+            }
+        };
+        let src = menu.config.gamedir.join(format!("{}.vpk", unknown.filename));
+        let filename = format!("{}.vpk", menu.config.format_filename(id, &item.title));
+        let dest = menu.config.gamedir.join(&filename);
+        if dest.exists() {
+            menu.logger.warn("MenuManage/ImportIdentified", &format!("{} already exists, so {}.vpk was not imported", dest.display(), unknown.filename));
+            continue
+        }
+        std::fs::rename(&src, &dest)?;
+        if let Some(addonlist) = addonlist.as_mut() {
+            //Keep the addon's enabled state under its new name
+            let enabled = addonlist.is_enabled(&format!("{}.vpk", unknown.filename));
+            addonlist.set_enabled(&filename, enabled);
+            b_addonlist_changed = true;
+        }
+
+        let mut download = crate::meta::DownloadEntry::from_item(item);
+        //The local copy may predate the latest version, in which case it should show up as outdated
+        if let Some(modified) = unknown.modified.and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok()) {
+            download.time_updated = download.time_updated.min(modified.as_secs() as usize);
+        }
+        println!("Imported {} as {}", item.title, filename);
+        menu.logger.logp(LogLevel::INFO, "MenuManage", &format!("Imported {}.vpk as {} ({})", unknown.filename, item.title, id));
+        menu.config.add_download(download);
+        imported += 1;
+    }
+    if let (true, Some(addonlist)) = (b_addonlist_changed, addonlist) {
+        addonlist.save()?;
+    }
+    menu.config.save()?;
+    println!("{}", console::style(format!("Imported {} addons.", imported)).bold());
+    Ok(())
+}
+
+/// Asks which addons should be enabled, and saves the changes to addonlist.txt
+fn prompt_toggle(menu: &mut util::MenuParams, addonlist: &mut AddonList, addons: &[AddonFile]) -> Result<(), Box<dyn std::error::Error>> {
+    if addons.is_empty() {
+        println!("There are no addons to enable or disable.");
+        return Ok(())
+    }
+    let labels: Vec<&String> = addons.iter().map(|addon| &addon.title).collect();
+    let defaults: Vec<bool> = addons.iter()
+        .map(|addon| addonlist.is_enabled(&format!("{}.vpk", addon.filename)))
+        .collect();
+    let selections = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select the addons that should be enabled (space to toggle, enter to confirm)")
+        .items(&labels)
+        .defaults(&defaults)
+        .interact()?;
+
+    let mut changed: usize = 0;
+    for (i, addon) in addons.iter().enumerate() {
+        let enabled = selections.contains(&i);
+        if enabled != defaults[i] {
+            addonlist.set_enabled(&format!("{}.vpk", addon.filename), enabled);
+            changed += 1;
+        }
+    }
+    if changed == 0 {
+        println!("No addons were changed.");
+        return Ok(())
+    }
+    addonlist.save()?;
+    println!("{}", console::style(format!("Changed {} addons.", changed)).bold());
+    menu.logger.logp(LogLevel::INFO, "MenuManage", &format!("Enabled/disabled {} addons", changed));
+    Ok(())
+}
+
+/// Enables or disables addons by id without the menu, returning one of the util::exit_code values.
+/// When `exclusive` is set, every other addon gets the opposite state
+pub fn command_set_enabled(menu: &mut util::MenuParams, inputs: &[String], enabled: bool, exclusive: bool) -> i32 {
+    let filenames = match Workshop::get_vpks_in_folder(&menu.config.gamedir) {
+        Ok(filenames) => filenames,
+        Err(err) => {
+            menu.logger.error("CmdSetEnabled/get_vpks_in_folder", &err);
+            return util::exit_code::ERROR
+        }
+    };
+    let mut addonlist = match AddonList::load(&menu.config.gamedir) {
+        Ok(addonlist) => addonlist,
+        Err(err) => {
+            menu.logger.error("CmdSetEnabled/AddonList", &format!("Could not read {}: {}", AddonList::get_path(&menu.config.gamedir).display(), err));
+            return util::exit_code::ERROR
+        }
+    };
+
+    let mut selected: Vec<&String> = Vec::with_capacity(inputs.len());
+    let mut b_any_unknown = false;
+    for input in inputs {
+        let id = util::Regexes::get_id_from_input(input);
+        let filename = filenames.iter().find(|filename| match &id {
+            Some(id) => util::Regexes::get_filename_addonid(filename).as_ref() == Some(id),
+            None => filename.eq_ignore_ascii_case(input.trim_end_matches(".vpk"))
+        });
+        match filename {
+            Some(filename) => selected.push(filename),
+            None => {
+                menu.logger.warn("CmdSetEnabled", &format!("No addon matching \"{}\" was found", input));
+                b_any_unknown = true;
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        return util::exit_code::ERROR
+    }
+
+    let mut changed: usize = 0;
+    for filename in &filenames {
+        let state = if selected.contains(&filename) {
+            enabled
+        } else if exclusive {
+            !enabled
+        } else {
+            continue
+        };
+        let file = format!("{}.vpk", filename);
+        if addonlist.is_enabled(&file) != state {
+            addonlist.set_enabled(&file, state);
+            changed += 1;
+        }
+    }
+    if changed == 0 {
+        println!("No addons were changed.");
+        return if b_any_unknown { util::exit_code::PARTIAL_FAILURE } else { util::exit_code::NOTHING_TO_DO }
+    }
+    if let Err(err) = addonlist.save() {
+        menu.logger.error("CmdSetEnabled/save", &err.to_string());
+        return util::exit_code::ERROR
+    }
+    println!("Changed {} addons.", changed);
+    menu.logger.logp(LogLevel::INFO, "CmdSetEnabled", &format!("Enabled/disabled {} addons", changed));
+    if b_any_unknown { util::exit_code::PARTIAL_FAILURE } else { util::exit_code::UPDATED }
+}
+
+/// Asks which addons to remove and whether to delete them or move them to the trash folder
+fn prompt_remove(menu: &mut util::MenuParams, addons: &[AddonFile]) -> Result<(), Box<dyn std::error::Error>> {
+    if addons.is_empty() {
+        println!("There are no addons to remove.");
+        return Ok(())
+    }
+    let labels: Vec<&String> = addons.iter().map(|addon| &addon.title).collect();
+    let selections = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select addons to remove (space to select, enter to confirm)")
+        .items(&labels)
+        .interact()?;
+    if selections.is_empty() {
+        println!("No addons were selected.");
+        return Ok(())
+    }
+
+    let trash = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("How should the {} addons be removed?", selections.len()))
+        .items(&[
+            format!("Move to the trash folder ({})", menu.config.gamedir.join(TRASH_FOLDER).display()),
+            "Delete permanently".to_string()
+        ])
+        .default(0)
+        .interact()? == 0;
+    if !Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Are you sure you want to remove {} addons?", selections.len()))
+        .default(false)
+        .interact()?
+    {
+        println!("Removal was cancelled.");
+        return Ok(())
+    }
+
+    for i in selections {
+        let addon = &addons[i];
+        remove_addon(menu, addon.id.as_deref(), &addon.filename, &addon.title, trash);
+    }
+    menu.config.save()?;
+    Ok(())
+}
+
+/// Removes addons by id without the menu, returning one of the util::exit_code values
+pub fn command(menu: &mut util::MenuParams, inputs: &[String], trash: bool, yes: bool) -> i32 {
+    let filenames = match Workshop::get_vpks_in_folder(&menu.config.gamedir) {
+        Ok(filenames) => filenames,
+        Err(err) => {
+            menu.logger.error("CmdRemove/get_vpks_in_folder", &err);
+            return util::exit_code::ERROR
+        }
+    };
+
+    let mut addons: Vec<AddonFile> = Vec::with_capacity(inputs.len());
+    let mut b_any_unknown = false;
+    for input in inputs {
+        let id = match util::Regexes::get_id_from_input(input) {
+            Some(id) => id,
+            None => {
+                menu.logger.warn("CmdRemove", &format!("Skipping \"{}\", it is not a workshop id or url", input));
+                b_any_unknown = true;
+                continue
+            }
+        };
+        let filename = filenames.iter()
+            .find(|filename| util::Regexes::get_filename_addonid(filename).as_deref() == Some(&id))
+            .cloned();
+        let title = match menu.config.get_download(&id) {
+            Some(downloaded) => downloaded.title.clone(),
+            None if filename.is_some() => id.clone(),
+            None => {
+                menu.logger.warn("CmdRemove", &format!("No addon with id {} was found", id));
+                b_any_unknown = true;
+                continue
+            }
+        };
+        addons.push(AddonFile {
+            filename: filename.unwrap_or_else(|| menu.config.get_addon_filename(&id)),
+            id: Some(id),
+            title
+        });
+    }
+    if addons.is_empty() {
+        //Only unknown ids were given, which is most likely a typo
+        menu.logger.error("CmdRemove", "None of the given addons were found, nothing was removed");
+        return util::exit_code::ERROR
+    }
+
+    let action = if trash { "move to the trash folder" } else { "delete" };
+    if !util::confirm_command(&format!("Are you sure you want to {} {} addons?", action, addons.len()), yes) {
+        println!("Removal was cancelled.");
+        return util::exit_code::NOT_CONFIRMED
+    }
+
+    let mut b_any_failed = false;
+    for addon in &addons {
+        if !remove_addon(menu, addon.id.as_deref(), &addon.filename, &addon.title, trash) {
+            b_any_failed = true;
+        }
+    }
+    if let Err(err) = menu.config.save() {
+        menu.logger.error("CmdRemove/save", &err.to_string());
+        return util::exit_code::ERROR
+    }
+    if b_any_failed || b_any_unknown {
+        util::exit_code::PARTIAL_FAILURE
+    } else {
+        util::exit_code::UPDATED
+    }
+}
+
+/// Removes an addon's vpk and stops tracking it. Does not save the config. Returns false if the file could not be removed
+pub fn remove_addon(menu: &mut util::MenuParams, id: Option<&str>, filename: &str, title: &str, trash: bool) -> bool {
+    let folder = if trash { Some(TRASH_FOLDER) } else { None };
+    if let Err(err) = remove_addon_file(&menu.config.gamedir, filename, folder) {
+        menu.logger.error("MenuManage/remove_addon", &format!("Could not remove {}: {}", title, err));
+        return false
+    }
+    if let Some(id) = id {
+        menu.config.remove_download(id);
+    }
+    let action = if trash { "Moved to trash:" } else { "Deleted" };
+    println!("{} {}", action, title);
+    menu.logger.logp(LogLevel::INFO, "MenuManage", &format!("{} {} ({}.vpk)", action, title, filename));
+    true
+}
+
+/// Deletes "<filename>.vpk" from the addons folder (or the parked folder, if a profile parked it),
+/// or moves it into `folder` (inside the addons folder) when given. Does nothing if the file does not exist
+pub fn remove_addon_file(gamedir: &Path, filename: &str, folder: Option<&str>) -> Result<(), std::io::Error> {
+    let file = format!("{}.vpk", filename);
+    let mut path = gamedir.join(&file);
+    if !path.exists() {
+        path = gamedir.join(crate::meta::PARKED_FOLDER).join(&file);
+        if !path.exists() {
+            return Ok(())
+        }
+    }
+    match folder {
+        Some(folder) => {
+            let dest_folder = gamedir.join(folder);
+            if path.parent() == Some(dest_folder.as_path()) {
+                return Ok(())
+            }
+            std::fs::create_dir_all(&dest_folder)?;
+            let dest = dest_folder.join(&file);
+            if dest.exists() {
+                std::fs::remove_file(&dest)?;
+            }
+            std::fs::rename(&path, dest)
+        },
+        None => std::fs::remove_file(&path)
+    }
+}
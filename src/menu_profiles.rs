@@ -0,0 +1,449 @@
+use crate::util;
+use crate::meta::{self, Profile};
+use crate::logger::LogLevel;
+use crate::addonlist::AddonList;
+
+use dialoguer::{theme::ColorfulTheme, Select, MultiSelect, Input, Confirm};
+use prettytable::{Table, Row, Cell, row};
+use console::style;
+use std::{collections::HashMap, fs, path::PathBuf};
+use steam_workshop_api::Workshop;
+
+/// What needs to change for a profile's addons to be exactly the ones loaded
+#[derive(Default)]
+struct Activation {
+    /// Addons that are in the addons folder but disabled in addonlist.txt
+    enable: Vec<String>,
+    /// Addons outside the profile that are enabled in addonlist.txt
+    disable: Vec<String>,
+    /// Addons in the profile that are in the parked folder
+    unpark: Vec<String>,
+    /// Addons outside the profile that are moved to the parked folder
+    park: Vec<String>,
+    /// Addons in the profile whose file cannot be found
+    missing: Vec<String>,
+    /// Filenames of enabled vpks that are not tracked. These are always disabled, as a profile could never unpark them
+    untracked: Vec<String>
+}
+
+impl Activation {
+    fn is_empty(&self) -> bool {
+        self.enable.is_empty() && self.disable.is_empty() && self.unpark.is_empty() && self.park.is_empty() && self.untracked.is_empty()
+    }
+}
+
+pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
+    if menu.config.profiles.is_empty() {
+        println!("There are no profiles yet.");
+    } else {
+        print_profiles(menu);
+    }
+    println!();
+
+    let choice = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Choose an option")
+        .items(&[
+            "Activate a profile",
+            "Create a profile",
+            "Edit a profile",
+            "Delete a profile",
+            "Return"
+        ])
+        .default(0)
+        .interact()?;
+
+    match choice {
+        0 => {
+            let profile = match prompt_choose_profile(menu, "Select a profile to activate")? {
+                Some(profile) => profile,
+                None => return Ok(None)
+            };
+            let park = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("How should addons outside of the profile be unloaded?")
+                .items(&[
+                    "Disable them in addonlist.txt",
+                    "Move them to the parked folder"
+                ])
+                .default(0)
+                .interact()? == 1;
+            let mut addonlist = AddonList::load(&menu.config.gamedir)?;
+            let activation = get_activation(menu, &profile, &addonlist, park);
+            print_activation(menu, &activation);
+            if activation.is_empty() {
+                println!("All addons in {} are already loaded.", profile.name);
+                set_active_profile(menu, &profile.name);
+                return Ok(None)
+            }
+            if Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Activate {}?", profile.name))
+                .default(true)
+                .interact()?
+            {
+                apply_activation(menu, &profile, &mut addonlist, &activation)?;
+            }
+        },
+        1 => {
+            let name: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Enter a name for the profile")
+                .interact()?;
+            let name = name.trim().to_string();
+            if menu.config.get_profile(&name).is_some() {
+                println!("A profile named {} already exists.", name);
+                return Ok(None)
+            }
+            let addons = prompt_profile_addons(menu, &[])?;
+            println!("Created profile {} with {} addons.", name, addons.len());
+            menu.logger.logp(LogLevel::INFO, "MenuProfiles", &format!("Created profile {} with {} addons", name, addons.len()));
+            menu.config.profiles.push(Profile { name, addons });
+            menu.config.save()?;
+        },
+        2 => {
+            let profile = match prompt_choose_profile(menu, "Select a profile to edit")? {
+                Some(profile) => profile,
+                None => return Ok(None)
+            };
+            let addons = prompt_profile_addons(menu, &profile.addons)?;
+            if let Some(existing) = menu.config.profiles.iter_mut().find(|existing| existing.name == profile.name) {
+                existing.addons = addons;
+            }
+            menu.config.save()?;
+            println!("Saved profile {}.", profile.name);
+        },
+        3 => {
+            let profile = match prompt_choose_profile(menu, "Select a profile to delete")? {
+                Some(profile) => profile,
+                None => return Ok(None)
+            };
+            if Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Delete profile {}? No addons will be removed.", profile.name))
+                .default(false)
+                .interact()?
+            {
+                menu.config.profiles.retain(|existing| existing.name != profile.name);
+                if menu.config.active_profile.as_deref() == Some(profile.name.as_str()) {
+                    menu.config.active_profile = None;
+                }
+                menu.config.save()?;
+                println!("Deleted profile {}.", profile.name);
+            }
+        },
+        _ => {}
+    }
+    Ok(None)
+}
+
+/// Creates a profile without the menu, returning one of the util::exit_code values.
+/// Uses the currently loaded tracked addons when no items are given
+pub fn command_create(menu: &mut util::MenuParams, name: &str, inputs: &[String]) -> i32 {
+    if menu.config.get_profile(name).is_some() {
+        menu.logger.error("CmdProfile/create", &format!("A profile named {} already exists", name));
+        return util::exit_code::ERROR
+    }
+    let addons = if inputs.is_empty() {
+        let addonlist = match AddonList::load(&menu.config.gamedir) {
+            Ok(addonlist) => addonlist,
+            Err(err) => {
+                menu.logger.error("CmdProfile/AddonList", &format!("Could not read {}: {}", AddonList::get_path(&menu.config.gamedir).display(), err));
+                return util::exit_code::ERROR
+            }
+        };
+        let files = menu.config.get_addon_files();
+        menu.config.downloads.iter()
+            .filter(|entry| is_loaded(menu, &files, &addonlist, &entry.publishedfileid))
+            .map(|entry| entry.publishedfileid.clone())
+            .collect()
+    } else {
+        let mut addons: Vec<String> = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            match util::Regexes::get_id_from_input(input) {
+                Some(id) if menu.config.get_download(&id).is_some() => {
+                    if !addons.contains(&id) {
+                        addons.push(id);
+                    }
+                },
+                _ => {
+                    menu.logger.error("CmdProfile/create", &format!("\"{}\" is not a tracked addon", input));
+                    return util::exit_code::ERROR
+                }
+            }
+        }
+        addons
+    };
+
+    println!("Created profile {} with {} addons.", name, addons.len());
+    menu.logger.logp(LogLevel::INFO, "CmdProfile", &format!("Created profile {} with {} addons", name, addons.len()));
+    menu.config.profiles.push(Profile { name: name.to_string(), addons });
+    if let Err(err) = menu.config.save() {
+        menu.logger.error("CmdProfile/save", &err.to_string());
+        return util::exit_code::ERROR
+    }
+    util::exit_code::UPDATED
+}
+
+/// Lists every profile without the menu
+pub fn command_list(menu: &mut util::MenuParams) -> i32 {
+    if menu.config.profiles.is_empty() {
+        println!("There are no profiles.");
+        return util::exit_code::NOTHING_TO_DO
+    }
+    print_profiles(menu);
+    util::exit_code::UPDATED
+}
+
+/// Shows what activating a profile would change, or how it differs from another profile when `other` is given
+pub fn command_diff(menu: &mut util::MenuParams, name: &str, other: Option<&str>, park: bool) -> i32 {
+    let profile = match find_profile(menu, name) {
+        Some(profile) => profile,
+        None => return util::exit_code::ERROR
+    };
+    if let Some(other) = other {
+        let other = match find_profile(menu, other) {
+            Some(profile) => profile,
+            None => return util::exit_code::ERROR
+        };
+        let mut b_any_difference = false;
+        for id in profile.addons.iter().filter(|id| !other.addons.contains(id)) {
+            println!("{} {}", style("-").red(), get_title(menu, id));
+            b_any_difference = true;
+        }
+        for id in other.addons.iter().filter(|id| !profile.addons.contains(id)) {
+            println!("{} {}", style("+").green(), get_title(menu, id));
+            b_any_difference = true;
+        }
+        if !b_any_difference {
+            println!("{} and {} contain the same addons.", profile.name, other.name);
+            return util::exit_code::NOTHING_TO_DO
+        }
+        return util::exit_code::UPDATED
+    }
+
+    let addonlist = match AddonList::load(&menu.config.gamedir) {
+        Ok(addonlist) => addonlist,
+        Err(err) => {
+            menu.logger.error("CmdProfile/AddonList", &format!("Could not read {}: {}", AddonList::get_path(&menu.config.gamedir).display(), err));
+            return util::exit_code::ERROR
+        }
+    };
+    let activation = get_activation(menu, &profile, &addonlist, park);
+    print_activation(menu, &activation);
+    if activation.is_empty() {
+        println!("All addons in {} are already loaded.", profile.name);
+        return util::exit_code::NOTHING_TO_DO
+    }
+    util::exit_code::UPDATED
+}
+
+/// Activates a profile without the menu, returning one of the util::exit_code values.
+/// Addons outside the profile are disabled in addonlist.txt, or moved to the parked folder when `park` is set
+pub fn command_activate(menu: &mut util::MenuParams, name: &str, park: bool) -> i32 {
+    let profile = match find_profile(menu, name) {
+        Some(profile) => profile,
+        None => return util::exit_code::ERROR
+    };
+    let mut addonlist = match AddonList::load(&menu.config.gamedir) {
+        Ok(addonlist) => addonlist,
+        Err(err) => {
+            menu.logger.error("CmdProfile/AddonList", &format!("Could not read {}: {}", AddonList::get_path(&menu.config.gamedir).display(), err));
+            return util::exit_code::ERROR
+        }
+    };
+    let activation = get_activation(menu, &profile, &addonlist, park);
+    print_activation(menu, &activation);
+    if activation.is_empty() {
+        println!("All addons in {} are already loaded.", profile.name);
+        set_active_profile(menu, &profile.name);
+        return if activation.missing.is_empty() { util::exit_code::NOTHING_TO_DO } else { util::exit_code::PARTIAL_FAILURE }
+    }
+    match apply_activation(menu, &profile, &mut addonlist, &activation) {
+        Ok(()) if activation.missing.is_empty() => util::exit_code::UPDATED,
+        Ok(()) => util::exit_code::PARTIAL_FAILURE,
+        Err(err) => {
+            menu.logger.error("CmdProfile/activate", &err.to_string());
+            util::exit_code::ERROR
+        }
+    }
+}
+
+fn find_profile(menu: &util::MenuParams, name: &str) -> Option<Profile> {
+    let profile = menu.config.get_profile(name).cloned();
+    if profile.is_none() {
+        menu.logger.error("CmdProfile", &format!("No profile named {} was found", name));
+    }
+    profile
+}
+
+fn get_title(menu: &util::MenuParams, id: &str) -> String {
+    match menu.config.get_download(id) {
+        Some(entry) => format!("{} ({})", entry.title, id),
+        None => id.to_string()
+    }
+}
+
+/// Gets the filename of an addon's vpk from Config::get_addon_files, ie "123.vpk" or "Some Map = 123.vpk"
+fn get_file(files: &HashMap<String, PathBuf>, id: &str) -> String {
+    files.get(id)
+        .and_then(|path| path.file_name())
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("{}.vpk", id))
+}
+
+/// Checks if a tracked addon is in the addons folder and enabled
+fn is_loaded(menu: &util::MenuParams, files: &HashMap<String, PathBuf>, addonlist: &AddonList, id: &str) -> bool {
+    let b_in_addons = files.get(id).is_some_and(|path| path.parent() == Some(menu.config.gamedir.as_path()));
+    b_in_addons && addonlist.is_enabled(&get_file(files, id))
+}
+
+fn get_activation(menu: &util::MenuParams, profile: &Profile, addonlist: &AddonList, park: bool) -> Activation {
+    let mut activation = Activation::default();
+    let gamedir = &menu.config.gamedir;
+    let files = menu.config.get_addon_files();
+    for entry in &menu.config.downloads {
+        let id = &entry.publishedfileid;
+        let file = get_file(&files, id);
+        let b_in_addons = files.get(id).is_some_and(|path| path.parent() == Some(gamedir.as_path()));
+        if profile.addons.contains(id) {
+            if !b_in_addons {
+                if files.contains_key(id) {
+                    activation.unpark.push(id.clone());
+                } else {
+                    activation.missing.push(id.clone());
+                    continue
+                }
+            }
+            if !addonlist.is_enabled(&file) {
+                activation.enable.push(id.clone());
+            }
+        } else if b_in_addons {
+            if park {
+                activation.park.push(id.clone());
+            } else if addonlist.is_enabled(&file) {
+                activation.disable.push(id.clone());
+            }
+        }
+    }
+    //Profiles can outlive the addons they list
+    for id in &profile.addons {
+        if menu.config.get_download(id).is_none() {
+            activation.missing.push(id.clone());
+        }
+    }
+    match Workshop::get_vpks_in_folder(gamedir) {
+        Ok(filenames) => {
+            for filename in filenames {
+                let file = format!("{}.vpk", filename);
+                let b_tracked = meta::get_filename_id(&filename).is_some_and(|id| menu.config.get_download(id).is_some());
+                if !b_tracked && addonlist.is_enabled(&file) {
+                    activation.untracked.push(file);
+                }
+            }
+        },
+        Err(err) => menu.logger.warn("MenuProfiles/get_activation", &format!("Could not list untracked addons: {}", err))
+    }
+    activation
+}
+
+fn print_activation(menu: &util::MenuParams, activation: &Activation) {
+    for id in &activation.unpark {
+        println!("{} {} (unpark)", style("+").green(), get_title(menu, id));
+    }
+    for id in activation.enable.iter().filter(|id| !activation.unpark.contains(id)) {
+        println!("{} {} (enable)", style("+").green(), get_title(menu, id));
+    }
+    for id in &activation.park {
+        println!("{} {} (park)", style("-").red(), get_title(menu, id));
+    }
+    for id in &activation.disable {
+        println!("{} {} (disable)", style("-").red(), get_title(menu, id));
+    }
+    for file in &activation.untracked {
+        println!("{} {} (disable, not tracked)", style("-").red(), file);
+    }
+    for id in &activation.missing {
+        println!("{} {} is missing and will not be loaded", style("!").yellow(), get_title(menu, id));
+    }
+}
+
+fn apply_activation(menu: &mut util::MenuParams, profile: &Profile, addonlist: &mut AddonList, activation: &Activation) -> Result<(), Box<dyn std::error::Error>> {
+    let gamedir = menu.config.gamedir.clone();
+    let files = menu.config.get_addon_files();
+    for id in &activation.unpark {
+        let file = get_file(&files, id);
+        fs::rename(gamedir.join(meta::PARKED_FOLDER).join(&file), gamedir.join(&file))?;
+    }
+    for id in &activation.park {
+        let file = get_file(&files, id);
+        crate::menu_manage::remove_addon_file(&gamedir, file.trim_end_matches(".vpk"), Some(meta::PARKED_FOLDER))?;
+    }
+    for id in &activation.enable {
+        addonlist.set_enabled(&get_file(&files, id), true);
+    }
+    for id in &activation.disable {
+        addonlist.set_enabled(&get_file(&files, id), false);
+    }
+    for file in &activation.untracked {
+        addonlist.set_enabled(file, false);
+    }
+    if !activation.enable.is_empty() || !activation.disable.is_empty() || !activation.untracked.is_empty() {
+        addonlist.save()?;
+    }
+    set_active_profile(menu, &profile.name);
+    println!("{}", style(format!("Activated profile {}.", profile.name)).bold());
+    menu.logger.logp(LogLevel::INFO, "MenuProfiles", &format!("Activated profile {}: {} loaded, {} unloaded",
+        profile.name,
+        activation.enable.len() + activation.unpark.len(),
+        activation.disable.len() + activation.park.len() + activation.untracked.len()
+    ));
+    Ok(())
+}
+
+fn set_active_profile(menu: &mut util::MenuParams, name: &str) {
+    menu.config.active_profile = Some(name.to_string());
+    if let Err(err) = menu.config.save() {
+        menu.logger.error("MenuProfiles/save", &format!("Could not save the active profile: {}", err));
+    }
+}
+
+fn print_profiles(menu: &util::MenuParams) {
+    let mut table = Table::new();
+    table.set_titles(row!["Profile", "Addons", "Active"]);
+    for profile in &menu.config.profiles {
+        let b_active = menu.config.active_profile.as_deref() == Some(profile.name.as_str());
+        table.add_row(Row::new(vec![
+            Cell::new(&profile.name),
+            Cell::new(&profile.addons.len().to_string()),
+            Cell::new(if b_active { "Yes" } else { "" })
+        ]));
+    }
+    table.printstd();
+}
+
+fn prompt_choose_profile(menu: &util::MenuParams, prompt: &str) -> Result<Option<Profile>, Box<dyn std::error::Error>> {
+    if menu.config.profiles.is_empty() {
+        println!("There are no profiles.");
+        return Ok(None)
+    }
+    let names: Vec<&String> = menu.config.profiles.iter().map(|profile| &profile.name).collect();
+    let choice = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(&names)
+        .default(0)
+        .interact()?;
+    Ok(menu.config.profiles.get(choice).cloned())
+}
+
+/// Asks which tracked addons belong in a profile
+fn prompt_profile_addons(menu: &util::MenuParams, selected: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let labels: Vec<&String> = menu.config.downloads.iter().map(|entry| &entry.title).collect();
+    let defaults: Vec<bool> = menu.config.downloads.iter()
+        .map(|entry| selected.contains(&entry.publishedfileid))
+        .collect();
+    let selections = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select the addons in this profile (space to toggle, enter to confirm)")
+        .items(&labels)
+        .defaults(&defaults)
+        .interact()?;
+    Ok(selections.into_iter()
+        .map(|i| menu.config.downloads[i].publishedfileid.clone())
+        .collect())
+}
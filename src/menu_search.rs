@@ -1,10 +1,12 @@
 use crate::util;
+use crate::logger::LogLevel;
 
 use console::style;
-use steam_workshop_api::{WorkshopSearchItem};
+use steam_workshop_api::{WorkshopItem, WorkshopSearchItem};
 use dialoguer::{theme::ColorfulTheme, Select, Input};
-use prettytable::{Table, Row, Cell, row, cell};
+use prettytable::{Table, Row, Cell, row};
 use chrono::prelude::*;
+use std::io::Read;
 
 pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
     let input : String = Input::new()
@@ -13,17 +15,28 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
 
     if let Some(fileid) = util::Regexes::get_id_from_workshop_url(&input) {
         let spinner = util::setup_spinner(format!("Fetching workshop item of id {}...", fileid));
-        match menu.workshop.get_published_file_details(&[fileid]) {
-            Ok(items) => {
-                let item = &items[0];
+        match util::get_file_details(std::slice::from_ref(&fileid)) {
+            Ok(details) => {
                 spinner.finish_and_clear();
+                let item = match details.get(&fileid) {
+                    Some(item) => item,
+                    None => {
+                        menu.logger.error("MenuSearch", &format!("{} was removed from the workshop or is private", fileid));
+                        return Ok(None)
+                    }
+                };
                 match menu.workshop.get_collection_details(&item.publishedfileid) {
                     Ok(Some(children)) => {
                         //Item is a collection of items
                         let spinner = util::setup_spinner("Fetching collection children...");
-                        match menu.workshop.get_published_file_details(&children) {
-                            Ok(cinfo) => {
+                        match util::get_file_details(&children) {
+                            Ok(details) => {
                                 spinner.finish_and_clear();
+                                //Removed or private children are skipped so they do not stop the rest from downloading
+                                for fileid in &details.missing {
+                                    menu.logger.warn("MenuSearch", &format!("Skipping {}, it was removed from the workshop or is private", fileid));
+                                }
+                                let cinfo = details.items;
                                 println!();
                                 println!("{}", style(format!("COLLETION: {}", item.title)).bold());
                                 let mut table = Table::new();
@@ -48,6 +61,7 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                                     .with_prompt("Select an option: ")
                                     .items(&[
                                         "Download Collection",
+                                        "Follow Collection (keep it in sync)",
                                         "Open collection in browser",
                                         "Return to menu"
                                     ])
@@ -55,19 +69,21 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                                 match choice {
                                     0 => {
                                         match util::download_addons(menu, &cinfo) {
-                                            Ok(()) => println!("Downloaded {} - {} items", &item.title, cinfo.len()),
+                                            Ok(report) => println!("Downloaded {} - {} items", &item.title, report.succeeded.len()),
                                             Err(err) => {
                                                 menu.logger.error("MenuSearch/children:download_addons", &err.to_string());
                                             }
                                         };
                                     },
                                     1 => {
+                                        crate::menu_collections::follow(menu, &item.publishedfileid);
+                                    },
+                                    2 => {
                                         webbrowser::open(&format!("https://steamcommunity.com/sharedfiles/filedetails/?id={}", &item.publishedfileid)).expect("Could not open in browser");
                                     },
-                                    2 | _ => return Ok(None)
+                                    _ => return Ok(None)
 
                                 }
-                                //TODO: Implement downloading
                             },
                             Err(err) => {
                                 spinner.finish_and_clear();
@@ -76,6 +92,7 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                         }
                     },
                     Ok(None) => {
+                        //Item is a single item
                         let choice = Select::with_theme(&ColorfulTheme::default())
                             .with_prompt("Select an option: ")
                             .items(&[
@@ -84,7 +101,13 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                                 "Return to menu"
                             ])
                             .interact().unwrap();
-                        //Item is a single item
+                        match choice {
+                            0 => download_item(menu, item),
+                            1 => {
+                                webbrowser::open(&format!("https://steamcommunity.com/sharedfiles/filedetails/?id={}", &item.publishedfileid)).expect("Could not open in browser");
+                            },
+                            _ => return Ok(None)
+                        }
                     },
                     Err(err) => {
                         menu.logger.error("MenuSearch/get_file_children_ids", &err.to_string());
@@ -108,7 +131,7 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
                         format!("{:2}. {} [{}]", i, console::style(&item.title).blue().bright().bold(), size)
                     })
                     .collect();
-                itms_dis.push(format!("{}", style("[ Cancel ]").cyan()));
+                itms_dis.push(style("[ Cancel ]").cyan().to_string());
                 //itms_dis.push(format!("{}", style("[ Next Page ➞ ]").green()));
     
                 println!();
@@ -130,29 +153,132 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
     Ok(None)
 }
 
+/// Downloads the given workshop ids or urls without the menu, expanding any collections into their children.
+/// Returns one of the util::exit_code values
+pub fn command(menu: &mut util::MenuParams, inputs: &[String]) -> i32 {
+    let mut fileids: Vec<String> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        match util::Regexes::get_id_from_input(input) {
+            Some(fileid) => {
+                if !fileids.contains(&fileid) {
+                    fileids.push(fileid);
+                }
+            },
+            None => menu.logger.warn("CmdDownload", &format!("Skipping \"{}\", it is not a workshop id or url", input))
+        }
+    }
+    if fileids.is_empty() {
+        println!("There are no items to download.");
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    //Replace any collections with their children
+    let spinner = util::setup_spinner("Fetching collection details...");
+    let mut itemids: Vec<String> = Vec::with_capacity(fileids.len());
+    for fileid in fileids {
+        match menu.workshop.get_collection_details(&fileid) {
+            Ok(Some(children)) => {
+                for child in children {
+                    if !itemids.contains(&child) {
+                        itemids.push(child);
+                    }
+                }
+            },
+            Ok(None) => {
+                if !itemids.contains(&fileid) {
+                    itemids.push(fileid);
+                }
+            },
+            Err(err) => {
+                spinner.abandon();
+                menu.logger.error("CmdDownload/get_collection_details", &err.to_string());
+                return util::exit_code::ERROR
+            }
+        }
+    }
+    spinner.finish_and_clear();
+
+    let spinner = util::setup_spinner(format!("Fetching details for {} items...", itemids.len()));
+    let details = match util::get_file_details(&itemids) {
+        Ok(details) => details,
+        Err(err) => {
+            spinner.abandon();
+            menu.logger.error("CmdDownload/get_file_details", &err.to_string());
+            return util::exit_code::ERROR
+        }
+    };
+    spinner.finish_and_clear();
+    //Removed or private items are skipped so they do not stop the rest from downloading
+    for fileid in &details.missing {
+        menu.logger.warn("CmdDownload", &format!("Skipping {}, it was removed from the workshop or is private", fileid));
+    }
+    if details.items.is_empty() {
+        menu.logger.error("CmdDownload", "None of the items could be found on the workshop");
+        return util::exit_code::ERROR
+    }
+
+    let total = details.items.len();
+    match util::download_addons(menu, &details.items) {
+        Ok(report) => {
+            menu.logger.logp(LogLevel::INFO, "CmdDownload", &format!("{} of {} items successfully downloaded, {} not found", report.succeeded.len(), total, details.missing.len()));
+            if report.failed.is_empty() {
+                println!("{}", style(format!("{} items successfully downloaded.", total)).bold());
+            }
+            if !details.missing.is_empty() && report.exit_code() == util::exit_code::UPDATED {
+                util::exit_code::PARTIAL_FAILURE
+            } else {
+                report.exit_code()
+            }
+        },
+        Err(err) => {
+            menu.logger.error("CmdDownload/download_addons", &err.to_string());
+            util::exit_code::ERROR
+        }
+    }
+}
+
+/// Reads workshop ids or urls from a file, one per line. Reads from stdin if the path is "-".
+/// Empty lines and lines starting with # are ignored
+pub fn read_inputs(path: &str) -> Result<Vec<String>, std::io::Error> {
+    let contents = if path == "-" {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        buf
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    Ok(contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
+}
+
 //UTIL Methods
 
-fn prompt_choose_item(menu: &util::MenuParams, items: &[WorkshopSearchItem], itms_dis: &[String]) -> ItemResult {
+fn prompt_choose_item(menu: &mut util::MenuParams, items: &[WorkshopSearchItem], itms_dis: &[String]) -> ItemResult {
     match Select::with_theme(&ColorfulTheme::default())
         .with_prompt(format!("Search Results ({} items, page {})", items.len(), 1))
-        .items(&itms_dis)
+        .items(itms_dis)
         .interact()
     {
         Ok(index) => {
             if index == items.len() {
                 return ItemResult::None
             }
-            return print_item(menu, &items[index])
+            print_item(menu, &items[index])
         },
-        Err(err) => eprintln!("{} {}", 
-            style("Error:").bold().red(),
-            style(err).red()
-        )
+        Err(err) => {
+            eprintln!("{} {}", 
+                style("Error:").bold().red(),
+                style(err).red()
+            );
+            ItemResult::None
+        }
     }
-    return ItemResult::None;
 }
 
-fn print_item(menu: &util::MenuParams, item: &steam_workshop_api::WorkshopSearchItem) -> ItemResult {
+fn print_item(menu: &mut util::MenuParams, item: &steam_workshop_api::WorkshopSearchItem) -> ItemResult {
     println!();
     println!("{}", style(&item.title).bold().underlined());
     println!("{} views\t{} favorites\t{} subscriptions", &item.views, &item.favorited, &item.subscriptions);
@@ -168,9 +294,7 @@ enum ItemResult {
     SearchSame,
     None
 }
-// util::download_addons(menu, &outdated).expect("update failed critically")
-
-fn prompt_item_options(menu: &util::MenuParams, item: &steam_workshop_api::WorkshopSearchItem) -> ItemResult {
+fn prompt_item_options(menu: &mut util::MenuParams, item: &steam_workshop_api::WorkshopSearchItem) -> ItemResult {
     match Select::with_theme(&ColorfulTheme::default())
         .with_prompt("Select an option")
         .default(0)
@@ -184,9 +308,7 @@ fn prompt_item_options(menu: &util::MenuParams, item: &steam_workshop_api::Works
     {
         Ok(option) => {
             match option {
-                0 => {
-                    println!("Can't download");
-                },
+                0 => download_item(menu, &item.to_item()),
                 1 => {
                     webbrowser::open(&format!("https://steamcommunity.com/sharedfiles/filedetails/?id={}", &item.publishedfileid)).ok();
                 },
@@ -197,5 +319,17 @@ fn prompt_item_options(menu: &util::MenuParams, item: &steam_workshop_api::Works
         Err(err) => menu.logger.error("MenuSearch/prompt_item_options", &err.to_string())
 
     }
-    return ItemResult::None
-}
\ No newline at end of file
+    ItemResult::None
+}
+
+/// Downloads a single item, which records it as a download on success
+fn download_item(menu: &mut util::MenuParams, item: &WorkshopItem) {
+    match util::download_addons(menu, std::slice::from_ref(item)) {
+        Ok(report) => {
+            if report.failed.is_empty() {
+                println!("{}", style(format!("Downloaded {}", item.title)).bold());
+            }
+        },
+        Err(err) => menu.logger.error("MenuSearch/download_item", &err.to_string())
+    }
+}
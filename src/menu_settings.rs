@@ -0,0 +1,215 @@
+use crate::util;
+use crate::meta;
+use crate::logger::LogLevel;
+
+use dialoguer::{theme::ColorfulTheme, Select, Input, Confirm};
+use console::style;
+use std::path::PathBuf;
+
+pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
+    loop {
+        let apikey_text = match &menu.config.apikey {
+            Some(_) => "(set)",
+            None => "(not set)"
+        };
+        let choice = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select a setting to change")
+            .items(&[
+                format!("Game Directory: {}", menu.config.get_game_path_str().unwrap_or("< no path >")),
+                format!("Steam API Key: {}", apikey_text),
+                format!("Include Name in Filenames: {}", if menu.config.include_name { "Yes" } else { "No" }),
+                format!("Concurrent Downloads: {}", menu.config.concurrency),
+                format!("Download Retries: {}", menu.config.retry.max_retries),
+                format!("Bandwidth Limit: {}", match menu.config.bandwidth_limit {
+                    Some(limit) => format!("{}/s", util::format_bytes(limit)),
+                    None => "Unlimited".to_string()
+                }),
+                "Return to menu".to_string()
+            ])
+            .default(0)
+            .interact()?;
+        println!();
+
+        let changed = match choice {
+            0 => match prompt_for_path() {
+                Some(path) => {
+                    menu.config.gamedir = path;
+                    true
+                },
+                None => {
+                    println!("{}", style("A valid directory was not specified, the game directory was not changed.").yellow());
+                    false
+                }
+            },
+            1 => {
+                let input: String = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Enter a Steam API key (leave empty to remove)")
+                    .allow_empty(true)
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        if input.is_empty() || (input.len() == 32 && input.chars().all(|c| c.is_ascii_hexdigit())) {
+                            Ok(())
+                        } else {
+                            Err("API keys are 32 hexadecimal characters, get one from https://steamcommunity.com/dev/apikey")
+                        }
+                    })
+                    .interact()?;
+                menu.config.apikey = if input.is_empty() { None } else { Some(input) };
+                true
+            },
+            2 => {
+                let include_name = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Include the addon name in downloaded filenames?")
+                    .default(menu.config.include_name)
+                    .interact()?;
+                if include_name != menu.config.include_name {
+                    menu.config.include_name = include_name;
+                    rename_addons(menu);
+                }
+                true
+            },
+            3 => {
+                menu.config.concurrency = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("How many addons should be downloaded at once? (1 - {})", meta::MAX_CONCURRENCY))
+                    .default(menu.config.concurrency)
+                    .validate_with(|input: &usize| -> Result<(), String> {
+                        if (1..=meta::MAX_CONCURRENCY).contains(input) {
+                            Ok(())
+                        } else {
+                            Err(format!("Must be between 1 and {}", meta::MAX_CONCURRENCY))
+                        }
+                    })
+                    .interact()?;
+                true
+            },
+            4 => {
+                menu.config.retry.max_retries = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("How many times should a failed download be retried? (0 - {})", meta::MAX_RETRIES))
+                    .default(menu.config.retry.max_retries)
+                    .validate_with(|input: &u32| -> Result<(), String> {
+                        if *input <= meta::MAX_RETRIES {
+                            Ok(())
+                        } else {
+                            Err(format!("Must be between 0 and {}", meta::MAX_RETRIES))
+                        }
+                    })
+                    .interact()?;
+                true
+            },
+            5 => {
+                let input: String = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Enter a combined download speed limit, ie 500K or 2M per second (leave empty for unlimited)")
+                    .allow_empty(true)
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        if input.is_empty() || util::parse_bytes(input).is_some() {
+                            Ok(())
+                        } else {
+                            Err("Not a valid speed, use a number with an optional K, M or G suffix")
+                        }
+                    })
+                    .interact()?;
+                menu.config.bandwidth_limit = util::parse_bytes(&input).filter(|limit| *limit > 0);
+                true
+            },
+            _ => return Ok(None)
+        };
+
+        if changed {
+            match menu.config.save() {
+                Ok(()) => {
+                    println!("{}", style("Settings saved.").green());
+                    menu.logger.logp(LogLevel::INFO, "MenuSettings", &format!("Changed setting {}", choice));
+                },
+                Err(err) => menu.logger.error("MenuSettings/save", &format!("Failed to save settings: {}", err))
+            }
+            println!();
+        }
+    }
+}
+
+/// Renames every tracked addon's vpk to match the include_name setting, wherever it is parked
+fn rename_addons(menu: &mut util::MenuParams) {
+    let files = menu.config.get_addon_files();
+    let mut renamed = 0;
+    for entry in &menu.config.downloads {
+        let path = match files.get(&entry.publishedfileid) {
+            Some(path) => path,
+            None => continue
+        };
+        let dest = path.with_file_name(format!("{}.vpk", menu.config.format_filename(&entry.publishedfileid, &entry.title)));
+        if dest == *path {
+            continue
+        }
+        match util::rename_addon_file(&menu.config.gamedir, path, &dest) {
+            Ok(()) => renamed += 1,
+            Err(err) => menu.logger.error("MenuSettings/rename", &format!("Could not rename {}: {}", path.display(), err))
+        }
+    }
+    if renamed > 0 {
+        println!("{}", style(format!("Renamed {} addons.", renamed)).green());
+    }
+}
+
+/// Prompts for the L4D2 addons folder, returning None if a valid directory was not chosen.
+/// Addons folders found in the Steam libraries are offered first
+pub fn prompt_for_path() -> Option<PathBuf> {
+    let detected = crate::steam::find_addons_folders();
+    let mut items: Vec<String> = detected.iter()
+        .map(|path| format!("Use detected install: {}", path.display()))
+        .collect();
+    items.extend([
+        "Use Current Directory",
+        "Choose a directory",
+        "Input a path manually"
+    ].iter().map(|item| item.to_string()));
+
+    match Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Set L4D2 Addons Folder")
+        .items(&items)
+        .default(0)
+        .interact()
+        .unwrap()
+    {
+        i if i < detected.len() => Some(detected[i].clone()),
+        i => match i - detected.len() {
+            0 => Some(std::env::current_dir().unwrap()),
+            1 => {
+                match tinyfiledialogs::open_file_dialog(
+                    "Choose where Left 4 Dead 2 is installed",
+                    "",
+                    Some((&["left4dead2.exe"], "left4dead2.exe"))
+                ) {
+                    Some(file_path) => {
+                        let path = PathBuf::from(file_path)
+                        .parent()
+                        .unwrap()
+                        .join("left4dead2")
+                        .join("addons");
+                        validate_path(path)
+                    },
+                    _ => None
+                }
+            },
+            2 => {
+                match Input::<String>::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Enter a path")
+                    .interact_on(&console::Term::stdout())
+                {
+                    Ok(path) => validate_path(PathBuf::from(path)),
+                    Err(e) => {
+                        eprintln!("An error occurred: {}", e);
+                        None
+                    }
+                }
+            },
+            _ => panic!("Item is not valid")
+        }
+    }
+}
+
+fn validate_path(path: PathBuf) -> Option<PathBuf> {
+    if path.is_dir() {
+        Some(path)
+    } else {
+        None
+    }
+}
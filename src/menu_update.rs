@@ -2,20 +2,98 @@ use crate::{util};
 use crate::logger::LogLevel;
 
 use indicatif::{HumanDuration};
-use dialoguer::{theme::ColorfulTheme, Confirm};
+use dialoguer::{theme::ColorfulTheme, Confirm, Select};
 use std::clone::Clone;
 use steam_workshop_api::{WorkshopItem};
 use console::style;
 
-const CONCURRENT_REQUESTS: usize = 4;
+/// Folder inside the addons folder that addons removed from the workshop are moved to when archived
+const ARCHIVE_FOLDER: &str = "archive";
+
+/// The result of checking the tracked downloads against the workshop
+struct UpdateCheck {
+    outdated: Vec<WorkshopItem>,
+    /// Ids of tracked downloads that no longer exist on the workshop
+    removed: Vec<String>
+}
 
 pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
+    let UpdateCheck { outdated, removed } = match check_updates(menu) {
+        Some(check) => check,
+        None => return Ok(None)
+    };
+    if !removed.is_empty() {
+        prompt_removed(menu, &removed)?;
+    }
+    if outdated.is_empty() {
+        return Ok(None)
+    }
+
+    let items = outdated.len();
+    println!();
+    if Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Are you sure you want to update {} workshop items?", items))
+        .default(true)
+        .interact()
+        .unwrap()
+    {
+        println!("Downloading {} items at a time", menu.get_concurrency());
+        println!();
+
+        let report = util::download_addons(menu, &outdated).expect("update failed critically");
+        let updated = report.succeeded.len();
+        println!("{}", console::style(format!("{} items successfully updated.", updated)).bold());
+        menu.logger.logp(LogLevel::INFO, "MenuUpdate", &format!("{} of {} items successfully updated", updated, items));
+    } else {
+        println!("Update was cancelled. Returning to menu.");
+    }
+    Ok(None)
+}
+
+/// Runs the update without the menu, returning one of the util::exit_code values.
+/// Skips the confirmation prompt when `yes` is set, so it can be used from cron or startup scripts
+pub fn command(menu: &mut util::MenuParams, yes: bool) -> i32 {
+    let UpdateCheck { outdated, removed } = match check_updates(menu) {
+        Some(check) => check,
+        None => return util::exit_code::ERROR
+    };
+    if !removed.is_empty() {
+        menu.logger.warn("CmdUpdate", &format!("{} addons were removed from the workshop and will be kept. Use the update menu to archive or delete them.", removed.len()));
+    }
+    if outdated.is_empty() {
+        menu.logger.logp(LogLevel::INFO, "CmdUpdate", "No items to update");
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    let items = outdated.len();
+    if !util::confirm_command(&format!("Are you sure you want to update {} workshop items?", items), yes) {
+        println!("Update was cancelled.");
+        return util::exit_code::NOT_CONFIRMED
+    }
+
+    match util::download_addons(menu, &outdated) {
+        Ok(report) => {
+            menu.logger.logp(LogLevel::INFO, "CmdUpdate", &format!("{} of {} items successfully updated", report.succeeded.len(), items));
+            if report.failed.is_empty() {
+                println!("{}", console::style(format!("{} items successfully updated.", items)).bold());
+            }
+            report.exit_code()
+        },
+        Err(err) => {
+            menu.logger.error("CmdUpdate/download_addons", &err.to_string());
+            util::exit_code::ERROR
+        }
+    }
+}
 
+/// Fetches the latest workshop info for all tracked downloads, finding the outdated ones and the ones removed from the workshop.
+/// Returns None if the details could not be fetched
+fn check_updates(menu: &util::MenuParams) -> Option<UpdateCheck> {
     //Get downloads from meta file & check if any
     let downloads = &menu.config.downloads;
     if downloads.is_empty() {
         println!("There are no items to update.");
-        return Ok(None)
+        return Some(UpdateCheck { outdated: Vec::new(), removed: Vec::new() })
     }
 
     //Get a array of addon ids
@@ -23,54 +101,80 @@ pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>,
         .iter()
         .map(|download| download.publishedfileid.clone())
         .collect();
-    
+
     //Using above list, get the latest workshop info (key is time_updated)
     let spinner = util::setup_spinner("Fetching Latest File Info...");
-    let details: Vec<WorkshopItem> = match menu.workshop.get_published_file_details(&fileids) {
+    let details = match util::get_file_details(&fileids) {
         Ok(details) => details,
-        Err(err) => { 
+        Err(err) => {
             spinner.abandon();
             menu.logger.error("MenuUpdate/get_file_details", &err.to_string());
-            return Ok(None)
+            return None
         }
     };
     spinner.finish_and_clear();
 
     let mut outdated: Vec<WorkshopItem> = Vec::with_capacity(fileids.len());
+    let mut removed: Vec<String> = Vec::new();
 
-    for (i, entry) in downloads.iter().enumerate() {
-        //Check if any entry in meta is outdated
-        if details[i].time_updated > entry.time_updated {
-            let duration = std::time::Duration::from_secs(details[i].time_updated as u64 - entry.time_updated as u64);
-            let hd = HumanDuration(duration);
-            println!("{title} is out of date. Last update was {hd} ago.", 
-                title=style(&entry.title).bold(), 
-                hd=hd);
-            outdated.push(details[i].clone());
+    for entry in downloads.iter() {
+        //Check if any entry in meta is outdated, matching by id as results can be missing or out of order
+        match details.get(&entry.publishedfileid) {
+            Some(item) if item.time_updated > entry.time_updated => {
+                let duration = std::time::Duration::from_secs(item.time_updated as u64 - entry.time_updated as u64);
+                let hd = HumanDuration(duration);
+                println!("{title} is out of date. Last update was {hd} ago.",
+                    title=style(&entry.title).bold(),
+                    hd=hd);
+                outdated.push(item.clone());
+            },
+            Some(_) => {},
+            None => {
+                println!("{title} was removed from the workshop.", title=style(&entry.title).bold());
+                removed.push(entry.publishedfileid.clone());
+            }
         }
     }
 
     if outdated.is_empty() {
-        println!("All {} addons are up-to-date.", details.len());
-        return Ok(None)
+        println!("All {} addons on the workshop are up-to-date.", details.items.len());
     }
+    Some(UpdateCheck { outdated, removed })
+}
 
-    let items = outdated.len();
+/// Asks what to do with each addon that was removed from the workshop: keep, archive or delete it
+fn prompt_removed(menu: &mut util::MenuParams, removed: &[String]) -> Result<(), Box<dyn std::error::Error>> {
     println!();
-    if Confirm::with_theme(&ColorfulTheme::default())
-        .with_prompt(format!("Are you sure you want to update {} workshop items?", items))
-        .default(true)
-        .interact()
-        .unwrap()
-    {
-        println!("Downloading {} items at a time", CONCURRENT_REQUESTS);
-        println!();
-
-        util::download_addons(menu, &outdated).expect("update failed critically");
-        println!("{}", console::style(format!("{} items successfully updated.", items)).bold());
-        menu.logger.logp(LogLevel::INFO, "MenuUpdate", &format!("{} items successfully updated", items));
-    } else {
-        println!("Update was cancelled. Returning to menu.");
+    for fileid in removed {
+        let title = match menu.config.get_download(fileid) {
+            Some(entry) => entry.title.clone(),
+            None => continue
+        };
+        let choice = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("{} was removed from the workshop. What should be done with it?", title))
+            .items(&[
+                "Keep it",
+                "Archive it (move to addons/archive)",
+                "Delete it"
+            ])
+            .default(0)
+            .interact()?;
+        let filename = menu.config.get_addon_filename(fileid);
+        let result = match choice {
+            1 => crate::menu_manage::remove_addon_file(&menu.config.gamedir, &filename, Some(ARCHIVE_FOLDER)),
+            2 => crate::menu_manage::remove_addon_file(&menu.config.gamedir, &filename, None),
+            _ => continue
+        };
+        match result {
+            Ok(()) => {
+                menu.config.remove_download(fileid);
+                menu.config.save()?;
+                let action = if choice == 1 { "Archived" } else { "Deleted" };
+                println!("{} {}", action, title);
+                menu.logger.logp(LogLevel::INFO, "MenuUpdate", &format!("{} removed addon {} ({})", action, title, fileid));
+            },
+            Err(err) => menu.logger.error("MenuUpdate/prompt_removed", &format!("Could not remove {}: {}", title, err))
+        }
     }
-    Ok(None)
-}
\ No newline at end of file
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use crate::util;
+use crate::logger::LogLevel;
+
+use console::style;
+use prettytable::{Table, Row, Cell, row};
+use rayon::prelude::*;
+use crate::vpk::Vpk;
+use std::path::Path;
+
+enum FileStatus {
+    Ok,
+    /// The file matches, but no hash was recorded for it yet. Contains the current hash
+    Unverified(String),
+    Missing,
+    /// The file could not be read or is not a valid VPK
+    Corrupted(String),
+    /// The file is a valid VPK, but does not match the recorded hash
+    Modified
+}
+
+pub fn handler(menu: &mut util::MenuParams) -> Result<Option<util::MenuResult>, Box<dyn std::error::Error>> {
+    verify(menu, false);
+    Ok(None)
+}
+
+/// Verifies all tracked addons without the menu, returning one of the util::exit_code values.
+/// Re-downloads any broken addons without asking when `yes` is set
+pub fn command(menu: &mut util::MenuParams, yes: bool) -> i32 {
+    verify(menu, yes)
+}
+
+fn verify(menu: &mut util::MenuParams, yes: bool) -> i32 {
+    if menu.config.downloads.is_empty() {
+        println!("There are no items to verify.");
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    let spinner = util::setup_spinner(format!("Verifying {} addons...", menu.config.downloads.len()));
+    let config = &*menu.config;
+    let results: Vec<(String, FileStatus)> = config.downloads
+        .par_iter()
+        .map(|entry| {
+            let path = config.get_addon_path(&entry.publishedfileid);
+            (entry.publishedfileid.clone(), check_file(&path, entry.sha256.as_deref()))
+        })
+        .collect();
+    spinner.finish_and_clear();
+
+    let mut table = Table::new();
+    table.set_titles(row!["Item Name", "Status"]);
+    let mut broken: Vec<String> = Vec::new();
+    let mut recorded: usize = 0;
+    for (fileid, status) in results {
+        let status_text = match status {
+            FileStatus::Ok => continue,
+            FileStatus::Unverified(hash) => {
+                //Nothing to compare against, so record the current file as the baseline
+                if let Some(index) = menu.config.downloads.iter().position(|entry| entry.publishedfileid == fileid) {
+                    menu.config.downloads[index].sha256 = Some(hash);
+                    recorded += 1;
+                }
+                continue
+            },
+            FileStatus::Missing => "Missing".to_string(),
+            FileStatus::Corrupted(reason) => format!("Corrupted ({})", reason),
+            FileStatus::Modified => "Modified".to_string()
+        };
+        let title = menu.config.get_download(&fileid).map(|entry| entry.title.clone()).unwrap_or_default();
+        table.add_row(Row::new(vec![
+            Cell::new(&title),
+            Cell::new(&status_text)
+        ]));
+        menu.logger.logp(LogLevel::WARN, "MenuVerify", &format!("{} ({}) is {}", title, fileid, status_text));
+        broken.push(fileid);
+    }
+
+    if recorded > 0 {
+        if let Err(err) = menu.config.save() {
+            menu.logger.error("MenuVerify/save", &format!("Could not save recorded hashes: {}", err));
+        }
+        println!("Recorded hashes for {} addons that had none.", recorded);
+    }
+
+    if broken.is_empty() {
+        println!("{}", style(format!("All {} addons are intact.", menu.config.downloads.len())).green());
+        return util::exit_code::NOTHING_TO_DO
+    }
+
+    table.printstd();
+    println!();
+    if !util::confirm_command(&format!("Re-download {} addons?", broken.len()), yes) {
+        println!("The addons were not re-downloaded.");
+        return util::exit_code::NOT_CONFIRMED
+    }
+
+    let spinner = util::setup_spinner("Fetching Latest File Info...");
+    let details = match util::get_file_details(&broken) {
+        Ok(details) => details,
+        Err(err) => {
+            spinner.abandon();
+            menu.logger.error("MenuVerify/get_file_details", &err.to_string());
+            return util::exit_code::ERROR
+        }
+    };
+    spinner.finish_and_clear();
+    for fileid in &details.missing {
+        menu.logger.warn("MenuVerify", &format!("{} was removed from the workshop and cannot be re-downloaded", fileid));
+    }
+
+    match util::download_addons(menu, &details.items) {
+        Ok(report) => {
+            if details.missing.is_empty() {
+                report.exit_code()
+            } else {
+                util::exit_code::PARTIAL_FAILURE
+            }
+        },
+        Err(err) => {
+            menu.logger.error("MenuVerify/download_addons", &err.to_string());
+            util::exit_code::ERROR
+        }
+    }
+}
+
+fn check_file(path: &Path, expected_hash: Option<&str>) -> FileStatus {
+    if !path.exists() {
+        return FileStatus::Missing
+    }
+    //Reading the directory tree catches truncated and garbled files even without a recorded hash
+    if let Err(err) = Vpk::open(path) {
+        return FileStatus::Corrupted(err.to_string())
+    }
+
+    match util::hash_file(path) {
+        Ok(hash) => match expected_hash {
+            Some(expected) if expected == hash => FileStatus::Ok,
+            Some(_) => FileStatus::Modified,
+            None => FileStatus::Unverified(hash)
+        },
+        Err(err) => FileStatus::Corrupted(err.to_string())
+    }
+}
@@ -1,15 +1,156 @@
-use steam_workshop_api;
-
-use std::{path::PathBuf, io, fs, env};
+use std::{collections::HashMap, path::{Path, PathBuf}, io::{self, Write}, fs, env};
 use serde::{Deserialize, Serialize};
 
+/// Missing fields use the values from Config::default, so new fields never make an existing config unreadable
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    /// Layout version of the config file, see CONFIG_SCHEMA
+    pub schema: u32,
     pub gamedir: PathBuf,
     pub apikey: Option<String>,
     pub downloads: Vec<DownloadEntry>,
+    /// Save addons as "<title> = <id>.vpk" instead of "<id>.vpk"
     pub include_name: bool,
-    pub version: String
+    /// Version of the program that last saved the config
+    pub version: String,
+    pub concurrency: usize,
+    pub retry: RetryPolicy,
+    /// Combined download speed limit in bytes per second
+    pub bandwidth_limit: Option<u64>,
+    pub profiles: Vec<Profile>,
+    /// Name of the profile that was last activated
+    pub active_profile: Option<String>,
+    pub collections: Vec<FollowedCollection>,
+    /// Where the config was loaded from and is saved to
+    #[serde(skip)]
+    pub path: PathBuf
+}
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+pub const MAX_CONCURRENCY: usize = 16;
+pub const MAX_RETRIES: u32 = 10;
+/// Name of the folder the config and log are stored in, inside the XDG config and state directories
+pub const APP_FOLDER: &str = "l4d2-workshop-downloader";
+pub const CONFIG_FILENAME: &str = "downloader_meta.json";
+pub const LOG_FILENAME: &str = "downloader.log";
+/// Environment variables that override the config and log paths
+pub const CONFIG_PATH_ENV: &str = "L4D2_DOWNLOADER_CONFIG";
+pub const LOG_PATH_ENV: &str = "L4D2_DOWNLOADER_LOG";
+/// Folder inside the addons folder that addons outside of the active profile are moved to when parked
+pub const PARKED_FOLDER: &str = "parked";
+/// Longest title kept in filenames when include_name is set
+const MAX_TITLE_LENGTH: usize = 100;
+
+/// The current layout version of the config file. Bump it and add a step to MIGRATIONS when the layout changes
+pub const CONFIG_SCHEMA: u32 = 3;
+
+/// Steps that upgrade a config's json in place. MIGRATIONS[0] upgrades schema 1 to 2, and so on
+const MIGRATIONS: [fn(&mut serde_json::Value); 2] = [
+    migrate_1_to_2,
+    migrate_2_to_3
+];
+
+/// Schema 1 is every config written before the schema field was added.
+/// Importing could track the same addon twice, so only the first entry of each id is kept
+fn migrate_1_to_2(value: &mut serde_json::Value) {
+    if let Some(downloads) = value.get_mut("downloads").and_then(|downloads| downloads.as_array_mut()) {
+        let mut seen: Vec<serde_json::Value> = Vec::with_capacity(downloads.len());
+        downloads.retain(|entry| {
+            let id = entry.get("publishedfileid").cloned().unwrap_or_default();
+            if seen.contains(&id) {
+                false
+            } else {
+                seen.push(id);
+                true
+            }
+        });
+    }
+}
+
+/// include_name used to default to on without having any effect, so the saved value was never a choice.
+/// It is turned off so existing addons keep their "<id>.vpk" names until it is turned on in the settings
+fn migrate_2_to_3(value: &mut serde_json::Value) {
+    if let Some(config) = value.as_object_mut() {
+        config.insert("include_name".to_string(), serde_json::Value::Bool(false));
+    }
+}
+
+/// Why the config could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(io::Error),
+    Parse(serde_json::Error),
+    /// The config was written by a newer version, which this version cannot safely rewrite
+    Newer(u32)
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(err) => write!(f, "Could not read the config: {}", err),
+            ConfigError::Parse(err) => write!(f, "The config is not valid: {}", err),
+            ConfigError::Newer(schema) => write!(f, "The config uses schema {}, but this version only supports up to {}. Please update the program", schema, CONFIG_SCHEMA)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How failed downloads are retried. The delay doubles every attempt, up to max_delay_ms
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64
+}
+
+/// A named set of tracked addons that can be loaded together
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// Ids of the addons in Config::downloads that are loaded when the profile is active
+    pub addons: Vec<String>
+}
+
+/// A workshop collection whose addons are kept in sync with the addons folder
+#[derive(Serialize, Deserialize)]
+pub struct FollowedCollection {
+    pub publishedfileid: String,
+    pub title: String,
+    /// Ids of the collection's children as of the last sync, used to find addons dropped from it
+    pub children: Vec<String>
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            schema: CONFIG_SCHEMA,
+            gamedir: PathBuf::new(),
+            apikey: None,
+            downloads: Vec::new(),
+            include_name: false,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            concurrency: DEFAULT_CONCURRENCY,
+            retry: RetryPolicy::default(),
+            bandwidth_limit: None,
+            profiles: Vec::new(),
+            active_profile: None,
+            collections: Vec::new(),
+            path: PathBuf::new()
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay_ms: 1000,
+            max_delay_ms: 30000
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -19,30 +160,34 @@ impl Config {
     }
 
     pub fn format_file(&self, item: &steam_workshop_api::WorkshopItem) -> String {
-        if self.include_name {
-            format!("{} = {}", title=item.title, id=item.publishedfileid)
+        self.format_filename(&item.publishedfileid, &item.title)
+    }
+
+    /// Gets the filename (without ".vpk") an addon is saved as, "<title> = <id>" when include_name is set or just the id.
+    /// Characters that cannot be in a filename are left out of the title
+    pub fn format_filename(&self, id: &str, title: &str) -> String {
+        let title: String = title.chars()
+            .filter(|c| !c.is_control() && !"<>:\"/\\|?*".contains(*c))
+            .take(MAX_TITLE_LENGTH)
+            .collect();
+        let title = title.trim().trim_end_matches('.');
+        if self.include_name && !title.is_empty() {
+            format!("{title} = {id}", title=title, id=id)
         }else{
-            item.publishedfileid.to_string()
+            id.to_string()
         }
     }
 
-    pub fn new(path: PathBuf) -> Config {
+    pub fn new(gamedir: PathBuf, path: PathBuf) -> Config {
         Config {
-            gamedir: path,
-            apikey: None,
-            downloads: Vec::<DownloadEntry>::new(),
-            include_name: true,
-            version: env!("CARGO_PKG_VERSION").to_string()
+            gamedir,
+            path,
+            ..Config::default()
         }
     }
 
     pub fn get_download(&self, id: &str) -> Option<&DownloadEntry> {
-        for item in self.downloads.iter() {
-            if item.publishedfileid == id {
-                return Some(&item);
-            }
-        }
-        return None
+        self.downloads.iter().find(|item| item.publishedfileid == id)
     }
 
     pub fn find_download(&self, item: &DownloadEntry) -> Option<usize> {
@@ -51,7 +196,7 @@ impl Config {
                 return Some(i);
             }
         }
-        return None
+        None
     }
 
     pub fn update_download(&mut self, item: DownloadEntry) {
@@ -67,34 +212,214 @@ impl Config {
         self.downloads.push(item);
     }
 
+    /// Removes the download with the given id, returning it if it existed
+    pub fn remove_download(&mut self, id: &str) -> Option<DownloadEntry> {
+        let index = self.downloads.iter().position(|item| item.publishedfileid == id)?;
+        Some(self.downloads.remove(index))
+    }
+
+    /// Gets the path of an addon's vpk, which is in the parked folder if it was parked by a profile.
+    /// If the addon is in neither folder, this is where it would be downloaded to
+    pub fn get_addon_path(&self, id: &str) -> PathBuf {
+        match self.get_addon_files().remove(id) {
+            Some(path) => path,
+            None => {
+                let filename = match self.get_download(id) {
+                    Some(entry) => self.format_filename(id, &entry.title),
+                    None => id.to_string()
+                };
+                self.gamedir.join(format!("{}.vpk", filename))
+            }
+        }
+    }
+
+    /// Gets the filename (without ".vpk") of an addon's vpk, see get_addon_path
+    pub fn get_addon_filename(&self, id: &str) -> String {
+        let path = self.get_addon_path(id);
+        path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(id).to_string()
+    }
 
-    pub fn load() -> Option<Config> {
-        match fs::File::open(env::current_dir().unwrap().join("downloader_meta.json")) {
-            Ok(file) => {
-                let reader = io::BufReader::new(file);
-                match serde_json::from_reader(reader) {
-                    Ok(u) => Some(u),
-                    Err(_e) => None
+    /// Finds the vpk of every addon in the addons and parked folders that is named after its id, keyed by id.
+    /// Addons in the addons folder take priority over parked ones
+    pub fn get_addon_files(&self) -> HashMap<String, PathBuf> {
+        let mut files: HashMap<String, PathBuf> = HashMap::new();
+        for folder in [self.gamedir.join(PARKED_FOLDER), self.gamedir.clone()].iter() {
+            let entries = match fs::read_dir(folder) {
+                Ok(entries) => entries,
+                Err(_) => continue
+            };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.extension().and_then(|ext| ext.to_str()) != Some("vpk") || !path.is_file() {
+                    continue
                 }
-            },
-            Err(_e) => None
+                if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()).and_then(get_filename_id) {
+                    files.insert(id.to_string(), path.clone());
+                }
+            }
         }
-        
+        files
+    }
+
+    pub fn get_profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name.eq_ignore_ascii_case(name))
     }
 
+    /// Loads the config at `path`, upgrading it to the current schema. Returns None if there is no config yet
+    pub fn load(path: &Path) -> Result<Option<Config>, ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(ConfigError::Read(err))
+        };
+        let mut value: serde_json::Value = serde_json::from_str(&contents).map_err(ConfigError::Parse)?;
+        let schema = value.get("schema").and_then(|schema| schema.as_u64()).unwrap_or(1) as u32;
+        if schema > CONFIG_SCHEMA {
+            return Err(ConfigError::Newer(schema))
+        }
+        for migration in MIGRATIONS.iter().skip(schema.saturating_sub(1) as usize) {
+            migration(&mut value);
+        }
+        if let Some(object) = value.as_object_mut() {
+            object.insert("schema".to_string(), CONFIG_SCHEMA.into());
+        }
+
+        let mut config: Config = serde_json::from_value(value).map_err(ConfigError::Parse)?;
+        config.path = path.to_path_buf();
+        config.version = env!("CARGO_PKG_VERSION").to_string();
+        Ok(Some(config))
+    }
+
+    /// Copies an unreadable config next to itself so it is not lost when a new one is saved, returning the copy's path
+    pub fn backup_unreadable(path: &Path) -> Result<PathBuf, io::Error> {
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let backup = get_sibling_path(path, &format!(".unreadable-{}", timestamp));
+        fs::copy(path, &backup)?;
+        Ok(backup)
+    }
+
+    /// Saves the config by writing a temporary file and renaming it over the old one, so a crash never leaves a partial file.
+    /// The previous config is kept as a .bak file
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let dir = env::current_dir().unwrap().join("downloader_meta.json");
-        fs::write(dir, serde_json::to_string(&self)?)?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = get_sibling_path(&self.path, ".tmp");
+        {
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(serde_json::to_string(&self)?.as_bytes())?;
+            file.sync_all()?;
+        }
+        if self.path.exists() {
+            fs::copy(&self.path, get_sibling_path(&self.path, ".bak"))?;
+        }
+        fs::rename(&temp_path, &self.path)?;
         Ok(())
     }
 
 }
 
-#[derive(Serialize, Deserialize)]
+/// Gets the id from a filename (without ".vpk") made by Config::format_filename, ie "Some Map = 123" or "123"
+pub fn get_filename_id(filename: &str) -> Option<&str> {
+    let id = match filename.rsplit_once(" = ") {
+        Some((_, id)) => id,
+        None => filename
+    };
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// Appends `suffix` to the file name, ie "downloader_meta.json" -> "downloader_meta.json.bak"
+fn get_sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// An advisory lock on the config, held for as long as the program runs so other instances do not overwrite it.
+/// A separate .lock file is locked, as saving replaces the config file itself
+pub struct ConfigLock {
+    _file: fs::File
+}
+
+impl ConfigLock {
+    /// Locks the config at `path`. If another instance holds the lock, waits for it when `wait` is set and returns None otherwise
+    pub fn acquire(path: &Path, wait: bool) -> Result<Option<ConfigLock>, io::Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(get_sibling_path(path, ".lock"))?;
+        match file.try_lock() {
+            Ok(()) => {},
+            Err(fs::TryLockError::WouldBlock) if wait => file.lock()?,
+            Err(fs::TryLockError::WouldBlock) => return Ok(None),
+            Err(fs::TryLockError::Error(err)) => return Err(err)
+        }
+        Ok(Some(ConfigLock { _file: file }))
+    }
+}
+
+/// Gets the config path: the override if given, then $L4D2_DOWNLOADER_CONFIG, then $XDG_CONFIG_HOME (or ~/.config)
+pub fn get_config_path(path_override: Option<PathBuf>) -> PathBuf {
+    path_override
+        .or_else(|| get_env_path(CONFIG_PATH_ENV))
+        .unwrap_or_else(|| get_base_dir("XDG_CONFIG_HOME", ".config").join(APP_FOLDER).join(CONFIG_FILENAME))
+}
+
+/// Gets the log path: $L4D2_DOWNLOADER_LOG, then $XDG_STATE_HOME (or ~/.local/state)
+pub fn get_log_path() -> PathBuf {
+    get_env_path(LOG_PATH_ENV)
+        .unwrap_or_else(|| get_base_dir("XDG_STATE_HOME", ".local/state").join(APP_FOLDER).join(LOG_FILENAME))
+}
+
+fn get_env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+/// Gets an XDG base directory, falling back to the default inside the home directory.
+/// Relative paths are ignored as the spec requires
+fn get_base_dir(var: &str, default: &str) -> PathBuf {
+    match get_env_path(var) {
+        Some(path) if path.is_absolute() => path,
+        _ => match get_env_path("HOME").or_else(|| get_env_path("USERPROFILE")) {
+            Some(home) => home.join(default),
+            None => env::current_dir().unwrap()
+        }
+    }
+}
+
+/// Moves a config from the current directory, where older versions kept it, to `path`.
+/// Returns true if a config was migrated
+pub fn migrate_config(path: &Path) -> Result<bool, io::Error> {
+    let old_path = env::current_dir()?.join(CONFIG_FILENAME);
+    if path.exists() || !old_path.is_file() || old_path == path {
+        return Ok(false)
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    //Renaming fails across filesystems, so fall back to copying
+    if fs::rename(&old_path, path).is_err() {
+        fs::copy(&old_path, path)?;
+        fs::remove_file(&old_path)?;
+    }
+    Ok(true)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DownloadEntry {
     pub title: String,
     pub publishedfileid: String,
-    pub time_updated: usize
+    pub time_updated: usize,
+    /// SHA-256 hash of the VPK as it was downloaded
+    #[serde(default)]
+    pub sha256: Option<String>
 }
 
 impl DownloadEntry {
@@ -102,7 +427,8 @@ impl DownloadEntry {
         DownloadEntry {
             title: item.title.clone(),
             publishedfileid: item.publishedfileid.clone(),
-            time_updated: item.time_updated
+            time_updated: item.time_updated,
+            sha256: None
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+use crate::keyvalues::{self, KeyValue};
+
+use std::{env, fs, path::{Path, PathBuf}};
+
+/// Steam's app id for Left 4 Dead 2
+pub const L4D2_APPID: &str = "550";
+const DEFAULT_INSTALLDIR: &str = "Left 4 Dead 2";
+
+/// Where Steam is usually installed on Linux, relative to the home directory
+const STEAM_ROOTS: [&str; 5] = [
+    ".steam/steam",
+    ".steam/root",
+    ".local/share/Steam",
+    ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+    "snap/steam/common/.local/share/Steam"
+];
+
+/// Finds the left4dead2/addons folder of every Steam library that has L4D2 installed
+pub fn find_addons_folders() -> Vec<PathBuf> {
+    let home = match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home),
+        None => return Vec::new()
+    };
+
+    let mut folders: Vec<PathBuf> = Vec::new();
+    for root in STEAM_ROOTS.iter().map(|root| home.join(root)) {
+        for library in get_libraries(&root) {
+            if let Some(addons) = get_addons_folder(&library) {
+                //The roots are often symlinks to each other
+                let addons = addons.canonicalize().unwrap_or(addons);
+                if !folders.contains(&addons) {
+                    folders.push(addons);
+                }
+            }
+        }
+    }
+    folders
+}
+
+/// Gets the library folders listed in a Steam install's libraryfolders.vdf, including the install itself
+fn get_libraries(root: &Path) -> Vec<PathBuf> {
+    let mut libraries: Vec<PathBuf> = Vec::new();
+    if root.is_dir() {
+        libraries.push(root.to_path_buf());
+    }
+    let contents = match fs::read_to_string(root.join("steamapps").join("libraryfolders.vdf")) {
+        Ok(contents) => contents,
+        Err(_) => return libraries
+    };
+    let entries = match keyvalues::parse(&contents) {
+        Ok(entries) => entries,
+        Err(_) => return libraries
+    };
+    let folders = entries.iter()
+        .find(|entry| entry.key.eq_ignore_ascii_case("libraryfolders"))
+        .and_then(KeyValue::children);
+    for folder in folders.into_iter().flatten() {
+        //Old files list "1" "/path", newer ones "1" { "path" "/path" "apps" { ... } }
+        let path = match folder.as_str() {
+            Some(path) if folder.key.chars().all(|c| c.is_ascii_digit()) => path,
+            Some(_) => continue,
+            None => match folder.get_str("path") {
+                Some(path) => path,
+                None => continue
+            }
+        };
+        let path = PathBuf::from(path);
+        if path.is_dir() && !libraries.contains(&path) {
+            libraries.push(path);
+        }
+    }
+    libraries
+}
+
+/// Gets the addons folder in a library if L4D2 is installed there
+fn get_addons_folder(library: &Path) -> Option<PathBuf> {
+    let steamapps = library.join("steamapps");
+    let manifest = fs::read_to_string(steamapps.join(format!("appmanifest_{}.acf", L4D2_APPID))).ok()?;
+    let installdir = keyvalues::parse(&manifest).ok()
+        .and_then(|entries| entries.into_iter().find(|entry| entry.key.eq_ignore_ascii_case("AppState")))
+        .and_then(|state| state.get_str("installdir").map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_INSTALLDIR.to_string());
+    let addons = steamapps.join("common").join(installdir).join("left4dead2").join("addons");
+    if addons.is_dir() {
+        Some(addons)
+    } else {
+        None
+    }
+}
@@ -1,22 +1,58 @@
 use indicatif::{ProgressBar, ProgressStyle};
-use std::{borrow::Cow, fs, io::Write};
+use std::{borrow::Cow, collections::HashMap, fs, io::Write, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}, sync::Mutex};
 use regex::Regex;
 use lazy_static::lazy_static;
 use tokio::runtime::Runtime;
 use futures::{stream, StreamExt};
+use sha2::{Digest, Sha256};
 
-use crate::meta::{Config, DownloadEntry};
-use crate::logger::Logger;
+use crate::meta::{Config, DownloadEntry, RetryPolicy};
+use crate::logger::{Logger, LogLevel};
+use crate::addonlist::AddonList;
 use steam_workshop_api::Workshop;
 
 pub struct MenuResult {
 
 }
 
+/// Exit codes returned by the non-interactive subcommands, so scripts can react to the outcome
+pub mod exit_code {
+    /// Items were downloaded, updated or removed successfully
+    pub const UPDATED: i32 = 0;
+    /// A fatal error occurred before anything could be done
+    pub const ERROR: i32 = 1;
+    /// Some items were downloaded, but at least one failed
+    pub const PARTIAL_FAILURE: i32 = 2;
+    /// There was nothing to download or update
+    pub const NOTHING_TO_DO: i32 = 3;
+    /// There were changes to make, but they were not confirmed (declined, or no terminal to ask on and no --yes)
+    pub const NOT_CONFIRMED: i32 = 4;
+}
+
 pub struct MenuParams<'a> {
     pub config: &'a mut Config,
     pub workshop: &'a Workshop,
-    pub logger: &'a Logger
+    pub logger: &'a Logger,
+    pub overrides: DownloadOverrides
+}
+
+/// Download settings given on the command line. These take priority over the config, but are never saved
+#[derive(Default)]
+pub struct DownloadOverrides {
+    pub concurrency: Option<usize>,
+    pub bandwidth_limit: Option<u64>
+}
+
+impl<'a> MenuParams<'a> {
+    /// Gets how many items are downloaded at once
+    pub fn get_concurrency(&self) -> usize {
+        self.overrides.concurrency.unwrap_or(self.config.concurrency).max(1)
+    }
+
+    /// Gets the combined download speed limit in bytes per second, if any
+    pub fn get_bandwidth_limit(&self) -> Option<u64> {
+        self.overrides.bandwidth_limit.or(self.config.bandwidth_limit).filter(|limit| *limit > 0)
+    }
 }
 
 pub fn setup_spinner(msg: impl Into<Cow<'static, str>>) -> ProgressBar {
@@ -26,17 +62,31 @@ pub fn setup_spinner(msg: impl Into<Cow<'static, str>>) -> ProgressBar {
     spinner
 }
 
+/// Asks for confirmation in the non-interactive subcommands. Returns true straight away when `yes` is set,
+/// and false when there is no terminal to ask on
+pub fn confirm_command(prompt: &str, yes: bool) -> bool {
+    if yes {
+        return true
+    }
+    if !std::io::IsTerminal::is_terminal(&std::io::stdin()) || !console::user_attended_stderr() {
+        println!("{} (use --yes to skip this prompt)", prompt);
+        return false
+    }
+    dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(true)
+        .interact()
+        .unwrap_or(false)
+}
+
 pub struct Regexes {}
 impl Regexes {
     pub fn get_filename_addonid(filename: &str) -> Option<String>  {
         lazy_static! {
             static ref REGEX: Regex = Regex::new(r"([0-9]{7,})").unwrap();
         }
-        if let Some(mat) = REGEX.find(&filename) {
-            return Some(filename[mat.start()..mat.end()].to_string());
-        } else {
-            None
-        }
+        //The id is last in names like "Some Map = 123"
+        REGEX.find_iter(filename).last().map(|mat| mat.as_str().to_string())
     }
     pub fn get_id_from_workshop_url(url: &str) -> Option<String> {
         lazy_static! {
@@ -50,6 +100,113 @@ impl Regexes {
         }
         None
     }
+    /// Gets a workshop id from either a workshop url or a plain numeric id
+    pub fn get_id_from_input(input: &str) -> Option<String> {
+        let input = input.trim();
+        if !input.is_empty() && input.chars().all(|c| c.is_ascii_digit()) {
+            return Some(input.to_string())
+        }
+        Regexes::get_id_from_workshop_url(input)
+    }
+}
+
+/// Gets the SHA-256 hash of a file as a lowercase hex string
+pub fn hash_file(path: &Path) -> Result<String, std::io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The latest workshop details for a list of ids, see get_file_details
+pub struct FileDetails {
+    pub items: Vec<steam_workshop_api::WorkshopItem>,
+    /// Ids that no longer exist on the workshop, ie they were removed, made private or banned
+    pub missing: Vec<String>
+}
+
+impl FileDetails {
+    pub fn get(&self, id: &str) -> Option<&steam_workshop_api::WorkshopItem> {
+        self.items.iter().find(|item| item.publishedfileid == id)
+    }
+}
+
+/// Gets the tags of a workshop item. WorkshopItemTag keeps its text private, so it is read back through serde
+pub fn get_item_tags(item: &steam_workshop_api::WorkshopItem) -> Vec<String> {
+    match serde_json::to_value(&item.tags) {
+        Ok(serde_json::Value::Array(tags)) => tags.iter()
+            .filter_map(|tag| tag.get("tag").and_then(|tag| tag.as_str()).map(str::to_string))
+            .collect(),
+        _ => Vec::new()
+    }
+}
+
+/// Fetches the latest details for each workshop id. Unlike Workshop::get_published_file_details, items that no longer
+/// exist do not fail the whole request, and are returned in FileDetails::missing instead.
+/// Items that exist but cannot be parsed, and responses without any details, are an error so they are never mistaken for removed items
+pub fn get_file_details(fileids: &[String]) -> Result<FileDetails, Box<dyn std::error::Error>> {
+    if fileids.is_empty() {
+        return Ok(FileDetails { items: Vec::new(), missing: Vec::new() })
+    }
+    let mut params: Vec<(String, String)> = Vec::with_capacity(fileids.len() + 1);
+    params.push(("itemcount".to_string(), fileids.len().to_string()));
+    for (i, fileid) in fileids.iter().enumerate() {
+        params.push((format!("publishedfileids[{}]", i), fileid.to_string()));
+    }
+    let response: serde_json::Value = reqwest::blocking::Client::new()
+        .post("https://api.steampowered.com/ISteamRemoteStorage/GetPublishedFileDetails/v1/")
+        .header("User-Agent", "L4D2-Workshop-Downloader")
+        .form(&params)
+        .send()?
+        .error_for_status()?
+        .json()?;
+
+    let mut details = FileDetails {
+        items: Vec::with_capacity(fileids.len()),
+        missing: Vec::new()
+    };
+    //Without the array nothing was looked up, so every id would wrongly be reported as missing
+    let results = match response["response"]["publishedfiledetails"].as_array() {
+        Some(results) => results,
+        None => return Err("The workshop response did not contain any file details".into())
+    };
+    for result in results {
+        let fileid = result["publishedfileid"].as_str().unwrap_or_default();
+        //A result of 1 is OK, anything else means the item could not be found
+        if result["result"].as_i64() == Some(1) {
+            match serde_json::from_value::<steam_workshop_api::WorkshopItem>(result.clone()) {
+                Ok(item) => details.items.push(item),
+                Err(err) => return Err(format!("Could not read the workshop details of {}: {}", fileid, err).into())
+            }
+        } else if !fileid.is_empty() && !details.missing.iter().any(|id| id == fileid) {
+            details.missing.push(fileid.to_string());
+        }
+    }
+    //Ids the response left out entirely were not found either
+    for fileid in fileids {
+        if details.get(fileid).is_none() && !details.missing.contains(fileid) {
+            details.missing.push(fileid.clone());
+        }
+    }
+    Ok(details)
+}
+
+/// Parses a byte amount such as "500K", "1.5M" or "2000". Uses the same units as format_bytes
+pub fn parse_bytes(input: &str) -> Option<u64> {
+    let input = input.trim().to_uppercase();
+    let input = input.trim_end_matches("/S").trim_end_matches('B').trim();
+    let (number, multiplier) = match input.chars().last()? {
+        'K' => (&input[..input.len() - 1], 1000.0),
+        'M' => (&input[..input.len() - 1], 1000000.0),
+        'G' => (&input[..input.len() - 1], 1000000000.0),
+        _ => (input, 1.0)
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    if number.is_finite() && number >= 0.0 {
+        Some((number * multiplier) as u64)
+    } else {
+        None
+    }
 }
 
 pub fn format_bytes(bytes: u64) -> String {
@@ -65,15 +222,313 @@ pub fn format_bytes(bytes: u64) -> String {
 }
 
 
-const CONCURRENT_REQUESTS: usize = 4;
-
 struct Download {
-    file: std::fs::File,
-    success: bool,
+    dest: PathBuf,
+    /// Where the addon was saved before, if that is not dest
+    old: Option<PathBuf>,
     item: steam_workshop_api::WorkshopItem,
+    /// The hash the file must have before it replaces dest, if it is locked to one
+    sha256: Option<String>
+}
+
+/// Why a single item failed to download
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The request could not be sent
+    Request(reqwest::Error),
+    /// The server responded with a non-success status code
+    Status(reqwest::StatusCode),
+    /// The connection failed while receiving the file
+    Stream(reqwest::Error),
+    /// The file could not be written to disk
+    Write(std::io::Error),
+    /// The received file does not match the size reported by the workshop
+    SizeMismatch { expected: u64, received: u64 },
+    /// The received file does not match the hash it is locked to
+    HashMismatch { expected: String, received: String }
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Request(err) => write!(f, "Request failed: {}", err),
+            DownloadError::Status(status) => write!(f, "Server responded with {}", status),
+            DownloadError::Stream(err) => write!(f, "Connection failed during download: {}", err),
+            DownloadError::Write(err) => write!(f, "Could not write file: {}", err),
+            DownloadError::SizeMismatch { expected, received } => write!(f, "Expected {} bytes, but received {} bytes", expected, received),
+            DownloadError::HashMismatch { expected, received } => write!(f, "Expected hash {}, but received a file with hash {}", expected, received)
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+/// The outcome of download_addons
+pub struct DownloadReport {
+    /// The ids of the items that were downloaded successfully
+    pub succeeded: Vec<String>,
+    /// The items that failed, with the reason they failed
+    pub failed: Vec<(steam_workshop_api::WorkshopItem, DownloadError)>
+}
+
+impl DownloadReport {
+    /// Gets the util::exit_code value matching this report
+    pub fn exit_code(&self) -> i32 {
+        if self.failed.is_empty() {
+            exit_code::UPDATED
+        } else if self.succeeded.is_empty() {
+            exit_code::ERROR
+        } else {
+            exit_code::PARTIAL_FAILURE
+        }
+    }
+}
+
+impl DownloadError {
+    /// Whether the partially downloaded file can be kept and resumed later
+    fn is_resumable(&self) -> bool {
+        matches!(self, DownloadError::Request(_) | DownloadError::Status(_) | DownloadError::Stream(_))
+    }
+
+    /// Whether the error is likely temporary (connection errors, server errors and truncated bodies)
+    fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Request(err) => !err.is_builder(),
+            DownloadError::Status(status) => status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            DownloadError::Stream(_) => true,
+            DownloadError::Write(_) => false,
+            DownloadError::SizeMismatch { expected, received } => received < expected,
+            DownloadError::HashMismatch { .. } => false
+        }
+    }
+}
+
+/// Gets the delay before the given retry attempt (starting at 1), using exponential backoff with jitter
+fn get_retry_delay(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let max_delay = policy.base_delay_ms
+        .saturating_mul(1u64 << (attempt - 1).min(32))
+        .min(policy.max_delay_ms);
+    //Random enough to spread out retries, without pulling in a rng
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0) as u64;
+    let jitter = if max_delay > 0 { nanos % (max_delay / 2 + 1) } else { 0 };
+    Duration::from_millis(max_delay / 2 + jitter)
+}
+
+/// Limits the combined speed of all downloads sharing it. Every chunk reserves the time it takes to transfer
+/// at the limit, and waits until that reservation has passed
+struct RateLimiter {
+    bytes_per_sec: u64,
+    next_free: Mutex<Instant>
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec,
+            next_free: Mutex::new(Instant::now())
+        }
+    }
+
+    async fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut next_free = self.next_free.lock().unwrap();
+            let now = Instant::now();
+            let start = if *next_free > now { *next_free } else { now };
+            *next_free = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            *next_free - now
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Everything shared between the concurrent downloads of download_addons
+struct Downloader<'a> {
+    client: reqwest::Client,
+    logger: &'a Logger,
+    policy: RetryPolicy,
+    limiter: Option<RateLimiter>
+}
+
+/// Downloads the item with download_file, retrying temporary failures as configured by the retry policy
+async fn download_file_with_retry(downloader: &Downloader<'_>, item: &steam_workshop_api::WorkshopItem, dest: &Path, sha256: Option<&str>) -> Result<String, DownloadError> {
+    let logger = downloader.logger;
+    let policy = &downloader.policy;
+    let attempts = policy.max_retries + 1;
+    let mut attempt: u32 = 1;
+    loop {
+        logger.logp(LogLevel::INFO, "download_addons", &format!("Downloading {} (attempt {}/{})", item, attempt, attempts));
+        match download_file(downloader, item, dest, sha256).await {
+            Err(err) if err.is_retryable() && attempt < attempts => {
+                let delay = get_retry_delay(policy, attempt);
+                logger.logp(LogLevel::WARN, "download_addons", &format!("Attempt {}/{} for {} failed: {}. Retrying in {} ms", attempt, attempts, item, err, delay.as_millis()));
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            },
+            Err(err) => {
+                logger.logp(LogLevel::ERROR, "download_addons", &format!("Attempt {}/{} for {} failed: {}", attempt, attempts, item, err));
+                return Err(err)
+            },
+            Ok(hash) => {
+                logger.logp(LogLevel::INFO, "download_addons", &format!("Downloaded {} on attempt {}/{}", item, attempt, attempts));
+                return Ok(hash)
+            }
+        }
+    }
+}
+
+/// Gets the path of the partial download for an item. The workshop's time_updated is part of the name,
+/// so a partial download is never resumed once the item has changed
+fn get_part_path(dest: &Path, item: &steam_workshop_api::WorkshopItem) -> PathBuf {
+    dest.with_file_name(format!("{}.{}.vpk.part", item.publishedfileid, item.time_updated))
+}
+
+/// Removes any partial downloads of the item other than `keep`, which belong to an older version
+fn remove_stale_parts(dest: &Path, item: &steam_workshop_api::WorkshopItem, keep: &Path) {
+    let dir = match dest.parent() {
+        Some(dir) => dir,
+        None => return
+    };
+    let prefix = format!("{}.", item.publishedfileid);
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_stale = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.starts_with(&prefix) && name.ends_with(".vpk.part") && path != keep,
+                None => false
+            };
+            if is_stale {
+                fs::remove_file(&path).ok();
+            }
+        }
+    }
+}
+
+/// Gets the start offset from a Content-Range header, ie "bytes 100-199/200"
+fn get_content_range_start(response: &reqwest::Response) -> Option<u64> {
+    let value = response.headers().get(reqwest::header::CONTENT_RANGE)?.to_str().ok()?;
+    value.strip_prefix("bytes ")?.split('-').next()?.parse().ok()
+}
+
+/// Downloads the item into `part_path`, resuming from the existing partial file with a Range request when possible.
+/// Falls back to downloading the full file when the server does not honour the range
+async fn download_part(downloader: &Downloader<'_>, item: &steam_workshop_api::WorkshopItem, part_path: &Path) -> Result<(), DownloadError> {
+    let (response, mut file) = loop {
+        let mut offset = fs::metadata(part_path).map(|meta| meta.len()).unwrap_or(0);
+        if item.file_size > 0 && offset > item.file_size {
+            fs::remove_file(part_path).map_err(DownloadError::Write)?;
+            offset = 0;
+        } else if offset > 0 && offset == item.file_size {
+            //Already fully downloaded, only needs to be verified & moved
+            return Ok(())
+        }
+
+        let mut request = downloader.client
+            .get(&item.file_url)
+            .header("User-Agent", "L4D2-Workshop-Downloader");
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let response = request.send().await.map_err(DownloadError::Request)?;
+        let status = response.status();
+
+        if offset > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT {
+            if get_content_range_start(&response) == Some(offset) {
+                let file = fs::OpenOptions::new().append(true).open(part_path).map_err(DownloadError::Write)?;
+                break (response, file)
+            }
+            //Server sent a different range than requested, start over
+            fs::remove_file(part_path).map_err(DownloadError::Write)?;
+        } else if offset > 0 && status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            fs::remove_file(part_path).map_err(DownloadError::Write)?;
+        } else if status.is_success() {
+            //Server ignored the range (or there was none), so the whole file is being sent
+            let file = fs::File::create(part_path).map_err(DownloadError::Write)?;
+            break (response, file)
+        } else {
+            return Err(DownloadError::Status(status))
+        }
+    };
+
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(DownloadError::Stream)?;
+        if let Some(limiter) = &downloader.limiter {
+            limiter.acquire(chunk.len()).await;
+        }
+        file.write_all(&chunk).map_err(DownloadError::Write)?;
+    }
+    file.sync_all().map_err(DownloadError::Write)?;
+    Ok(())
+}
+
+/// Downloads the item into a .part file next to `dest`, only replacing `dest` once the download is verified, including
+/// against `sha256` when given. Interrupted downloads keep their .part file so they can be resumed, and any existing file
+/// at `dest` is left untouched. Returns the hash of the downloaded file
+async fn download_file(downloader: &Downloader<'_>, item: &steam_workshop_api::WorkshopItem, dest: &Path, sha256: Option<&str>) -> Result<String, DownloadError> {
+    let part_path = get_part_path(dest, item);
+    remove_stale_parts(dest, item, &part_path);
+
+    let result = match download_part(downloader, item, &part_path).await {
+        Ok(()) => {
+            let received = fs::metadata(&part_path).map(|meta| meta.len()).map_err(DownloadError::Write)?;
+            if received == 0 || (item.file_size > 0 && received != item.file_size) {
+                Err(DownloadError::SizeMismatch { expected: item.file_size, received })
+            } else {
+                match hash_file(&part_path).map_err(DownloadError::Write) {
+                    Ok(hash) if sha256.map(|expected| expected.eq_ignore_ascii_case(&hash)).unwrap_or(true) => {
+                        fs::rename(&part_path, dest).map(|_| hash).map_err(DownloadError::Write)
+                    },
+                    Ok(hash) => Err(DownloadError::HashMismatch { expected: sha256.unwrap_or_default().to_string(), received: hash }),
+                    Err(err) => Err(err)
+                }
+            }
+        },
+        Err(err) => Err(err)
+    };
+
+    if let Err(err) = &result {
+        if !err.is_resumable() {
+            fs::remove_file(&part_path).ok();
+        }
+    }
+    result
+}
+
+/// Checks if two paths point to the same file, ignoring case as Windows does
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// Renames an addon's vpk, keeping its enabled state in addonlist.txt
+pub fn rename_addon_file(gamedir: &Path, old: &Path, new: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::rename(old, new)?;
+    copy_enabled_state(gamedir, old, new)
+}
+
+/// Gives `new` the enabled state `old` has in addonlist.txt, after an addon was saved under a new name
+fn copy_enabled_state(gamedir: &Path, old: &Path, new: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let (old_file, new_file) = match (old.file_name().and_then(|name| name.to_str()), new.file_name().and_then(|name| name.to_str())) {
+        (Some(old_file), Some(new_file)) => (old_file, new_file),
+        _ => return Ok(())
+    };
+    let mut addonlist = AddonList::load(gamedir)?;
+    let enabled = addonlist.is_enabled(old_file);
+    if addonlist.is_enabled(new_file) != enabled {
+        addonlist.set_enabled(new_file, enabled);
+        addonlist.save()?;
+    }
+    Ok(())
 }
 
-pub fn download_addons(menu: &mut MenuParams, items: &[steam_workshop_api::WorkshopItem]) -> Result<(), Box<dyn std::error::Error>> {
+/// Downloads all items into the game directory, recording every successful download in the config.
+/// Prints a summary of any items that failed
+pub fn download_addons(menu: &mut MenuParams, items: &[steam_workshop_api::WorkshopItem]) -> Result<DownloadReport, Box<dyn std::error::Error>> {
+    download_locked_addons(menu, items, &HashMap::new())
+}
+
+/// Like download_addons, but items with an entry in `hashes` (by id) must match that SHA-256 hash.
+/// Files that do not match fail without replacing the installed file or being recorded in the config
+pub fn download_locked_addons(menu: &mut MenuParams, items: &[steam_workshop_api::WorkshopItem], hashes: &HashMap<String, String>) -> Result<DownloadReport, Box<dyn std::error::Error>> {
     let progress = ProgressBar::new(items.len() as u64)
     .with_style(ProgressStyle::default_bar()
         .template("{spinner:.green} [{elapsed_precise}] [{bar:60.cyan/blue}] {pos} / {len} items updated ({percent}%)")
@@ -88,91 +543,267 @@ pub fn download_addons(menu: &mut MenuParams, items: &[steam_workshop_api::Works
         //"―\\|/―\\|/―"
     );
 
+    let files = menu.config.get_addon_files();
     let mut downloads: Vec<Download> = Vec::with_capacity(items.len());
     for item in items {
-        
-        let dest = {
-            let fname = menu.config.gamedir.join(format!("{}.vpk", item.publishedfileid));
-            fs::File::create(fname).expect("Could not create file")
+        //Keep parked addons parked, but use the current name in case the title or include_name changed
+        let filename = format!("{}.vpk", menu.config.format_file(item));
+        let existing = files.get(&item.publishedfileid);
+        let dest = match existing {
+            Some(path) => path.with_file_name(&filename),
+            None => menu.config.gamedir.join(&filename)
         };
         let download = Download {
-            file: dest,
+            old: existing.filter(|path| !is_same_path(path, &dest)).cloned(),
+            dest,
             item: item.clone(),
-            success: false
+            sha256: hashes.get(&item.publishedfileid).cloned()
         };
         downloads.push(download);
     }
     progress.tick();
     progress.enable_steady_tick(500);
 
-    let client = reqwest::Client::new();
+    let concurrency = menu.get_concurrency();
+    let downloader = Downloader {
+        client: reqwest::Client::new(),
+        logger: menu.logger,
+        policy: menu.config.retry.clone(),
+        limiter: menu.get_bandwidth_limit().map(RateLimiter::new)
+    };
 
+    let mut report = DownloadReport {
+        succeeded: Vec::with_capacity(items.len()),
+        failed: Vec::new()
+    };
     let rt = Runtime::new()?;
     rt.block_on(async {
         stream::iter(downloads)
-        .map(|mut download: Download| {
-            let client = &client;
+        .map(|download: Download| {
+            let downloader = &downloader;
             let pb = &progress;
             async move {
-                pb.set_message(format!("{}", &download.item.title));
-                match client
-                    .get(&download.item.file_url)
-                    .header("User-Agent", "L4D2-Workshop-Downloader")
-                    .send()
-                    .await
-                    
-                {
-                    Ok(response) => {
-                        let mut stream = response.bytes_stream();
-                        while let Some(result) = stream.next().await {
-                            match result {
-                                Ok(chunk) => {
-                                    if let Err(err) = download.file.write(&chunk) {
-                                        println!("[{}] Write Error: {}", &download.item.publishedfileid, err);
-                                        break;
-                                    }
-                                
-                                },
-                                Err(err) => {
-                                    println!("{}\n{}", 
-                                        console::style(format!("Download for {} failed:\n", &download.item.title)).red().bold(),
-                                        console::style(err).red()
-                                    );
-                                    break;
-                                }
-                            }
+                pb.set_message(download.item.title.clone());
+                let result = download_file_with_retry(downloader, &download.item, &download.dest, download.sha256.as_deref()).await;
+                (download, result)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .for_each(|(download, result)| {
+            progress.inc(1);
+            match result {
+                Ok(hash) => {
+                    let mut entry = DownloadEntry::from_item(&download.item);
+                    entry.sha256 = Some(hash);
+                    match menu.config.find_download(&entry) {
+                        Some(index) => menu.config.downloads[index] = entry,
+                        None => menu.config.add_download(entry)
+                    }
+                    if let Err(err) = menu.config.save() {
+                        menu.logger.logp(LogLevel::ERROR, "download_addons", &format!("Could not save config: {}", err));
+                    }
+                    if let Some(old) = &download.old {
+                        //The addon was saved under a different name before, which would otherwise be loaded twice
+                        if let Err(err) = fs::remove_file(old).map_err(|err| err.into()).and_then(|_| copy_enabled_state(&menu.config.gamedir, old, &download.dest)) {
+                            menu.logger.logp(LogLevel::ERROR, "download_addons", &format!("Could not remove the old file {}: {}", old.display(), err));
                         }
-                        download.file.flush().ok();
-                        download.success = true;
-                    },
-                    Err(err) => {
-                        println!("Download failure for {}: {}", &download.item, err);
                     }
+                    let filename = download.dest.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                    progress.println(format!("Updated {} as {}", &download.item.title, filename));
+                    report.succeeded.push(download.item.publishedfileid);
+                },
+                Err(err) => {
+                    progress.println(format!("{}\n{}",
+                        console::style(format!("Download for {} failed:", &download.item.title)).red().bold(),
+                        console::style(&err).red()
+                    ));
+                    report.failed.push((download.item, err));
                 }
-                download
             }
+            async {}
         })
-        .buffer_unordered(CONCURRENT_REQUESTS)
-        .for_each(|download| {
-            progress.inc(1);
-            let pb = &progress;
+        .await;
+        progress.finish_and_clear();
 
-            let entry = DownloadEntry::from_item(&download.item);
+    });
 
-            match menu.config.find_download(&entry) {
-                Some(index) => menu.config.downloads[index] = entry,
-                None => menu.config.add_download(entry)
-            }
+    if !report.failed.is_empty() {
+        println!("{}", console::style(format!("{} of {} items failed to download:", report.failed.len(), items.len())).red().bold());
+        for (item, err) in &report.failed {
+            println!("  {} ({}): {}", item.title, item.publishedfileid, err);
+        }
+    }
+    Ok(report)
+}
 
-            menu.config.save().ok();
 
-            async move {
-                pb.println(format!("Updated {} as {}.vpk", &download.item.title, &download.item.publishedfileid));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, net::{TcpListener, TcpStream}, sync::Arc, thread};
+
+    /// How the stand-in server answers a single request
+    enum Reply {
+        /// Sends a 200 with the full Content-Length, but drops the connection after this many bytes
+        CutOff(usize),
+        /// Honours the Range header with a 206
+        Range,
+        /// Ignores the Range header and sends the whole file
+        Full
+    }
+
+    /// Serves `body` once for each reply in order, returning the url and the headers of every request received
+    fn serve(body: Vec<u8>, replies: Vec<Reply>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/addon.vpk", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let headers = read_headers(&mut stream).to_ascii_lowercase();
+                let start: usize = headers.lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse().ok())
+                    .unwrap_or(0);
+                received.lock().unwrap().push(headers);
+                match reply {
+                    Reply::CutOff(sent) => {
+                        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).unwrap();
+                        stream.write_all(&body[..sent]).unwrap();
+                    },
+                    Reply::Range => {
+                        write!(stream, "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                            body.len() - start, start, body.len() - 1, body.len()).unwrap();
+                        stream.write_all(&body[start..]).unwrap();
+                    },
+                    Reply::Full => {
+                        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).unwrap();
+                        stream.write_all(&body).unwrap();
+                    }
+                }
+                stream.flush().unwrap();
             }
-        })
-        .await;
-        progress.finish_and_clear();
+        });
+        (url, requests)
+    }
 
-    });
-    Ok(())
-}
\ No newline at end of file
+    fn read_headers(stream: &mut TcpStream) -> String {
+        let mut headers: Vec<u8> = Vec::new();
+        let mut byte = [0u8; 1];
+        while !headers.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+            headers.push(byte[0]);
+        }
+        String::from_utf8_lossy(&headers).into_owned()
+    }
+
+    fn get_test_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("l4d2-download-test-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn get_item(url: &str, file_size: usize) -> steam_workshop_api::WorkshopItem {
+        serde_json::from_value(serde_json::json!({
+            "result": 1,
+            "publishedfileid": "1234567",
+            "creator": "",
+            "creator_app_id": 550,
+            "consumer_app_id": 550,
+            "filename": "",
+            "file_size": file_size,
+            "file_url": url,
+            "preview_url": "",
+            "hcontent_preview": "",
+            "title": "Test Addon",
+            "description": "",
+            "time_created": 1,
+            "time_updated": 2,
+            "subscriptions": 0,
+            "favorited": 0,
+            "views": 0,
+            "tags": []
+        })).unwrap()
+    }
+
+    /// Cuts the first download off partway through, then downloads again with the server answering `second`
+    fn download_after_cut_off(test: &str, second: Reply) {
+        let body: Vec<u8> = (0..100000u32).map(|i| (i % 251) as u8).collect();
+        let (url, requests) = serve(body.clone(), vec![Reply::CutOff(40000), second]);
+        let dir = get_test_dir(test);
+        let dest = dir.join("1234567.vpk");
+        let item = get_item(&url, body.len());
+        let logger = Logger::new(dir.join("test.log"));
+        let downloader = Downloader {
+            client: reqwest::Client::new(),
+            logger: &logger,
+            policy: RetryPolicy::default(),
+            limiter: None
+        };
+        let rt = Runtime::new().unwrap();
+
+        let err = rt.block_on(download_file(&downloader, &item, &dest, None)).unwrap_err();
+        assert!(err.is_resumable(), "{}", err);
+        let part_path = get_part_path(&dest, &item);
+        assert_eq!(fs::metadata(&part_path).unwrap().len(), 40000);
+        assert!(!dest.exists());
+
+        let hash = rt.block_on(download_file(&downloader, &item, &dest, None)).unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=40000-"), "{}", requests[1]);
+        assert_eq!(fs::read(&dest).unwrap(), body);
+        assert_eq!(hash, format!("{:x}", Sha256::digest(&body)));
+        assert!(!part_path.exists());
+    }
+
+    #[test]
+    fn keeps_installed_file_when_hash_does_not_match() {
+        let body = b"new version".to_vec();
+        let (url, _) = serve(body.clone(), vec![Reply::Full, Reply::Full]);
+        let dir = get_test_dir("hash");
+        let dest = dir.join("1234567.vpk");
+        fs::write(&dest, b"installed version").unwrap();
+        let item = get_item(&url, body.len());
+        let logger = Logger::new(dir.join("test.log"));
+        let downloader = Downloader {
+            client: reqwest::Client::new(),
+            logger: &logger,
+            policy: RetryPolicy::default(),
+            limiter: None
+        };
+        let rt = Runtime::new().unwrap();
+
+        let err = rt.block_on(download_file(&downloader, &item, &dest, Some("0000"))).unwrap_err();
+        assert!(matches!(err, DownloadError::HashMismatch { .. }), "{}", err);
+        assert_eq!(fs::read(&dest).unwrap(), b"installed version");
+        assert!(!get_part_path(&dest, &item).exists());
+
+        let hash = format!("{:x}", Sha256::digest(&body));
+        assert_eq!(rt.block_on(download_file(&downloader, &item, &dest, Some(&hash.to_uppercase()))).unwrap(), hash);
+        assert_eq!(fs::read(&dest).unwrap(), body);
+    }
+
+    #[test]
+    fn resumes_cut_off_download_with_range() {
+        download_after_cut_off("range", Reply::Range);
+    }
+
+    #[test]
+    fn restarts_download_when_range_is_ignored() {
+        download_after_cut_off("full", Reply::Full);
+    }
+
+    #[test]
+    fn named_files_resolve_to_their_id() {
+        let mut config = crate::meta::Config { include_name: true, ..Default::default() };
+        let filename = config.format_filename("1234567", "Left 4 Dead: 2020 Remix?");
+        assert_eq!(filename, "Left 4 Dead 2020 Remix = 1234567");
+        assert_eq!(crate::meta::get_filename_id(&filename), Some("1234567"));
+        assert_eq!(Regexes::get_filename_addonid(&filename).as_deref(), Some("1234567"));
+        config.include_name = false;
+        assert_eq!(config.format_filename("1234567", "Map"), "1234567");
+        assert_eq!(crate::meta::get_filename_id("custom"), None);
+    }
+}
@@ -0,0 +1,512 @@
+//! Reader for Valve's VPK archives (version 1 and 2). Only the header and directory tree are parsed,
+//! file contents are read on demand from the directory file or its numbered archives (ie "pak01_000.vpk").
+
+use std::{fmt, fs, io::{self, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+
+/// The first 4 bytes of every VPK file, 0x55aa1234 in little endian
+pub const SIGNATURE: [u8; 4] = [0x34, 0x12, 0xaa, 0x55];
+/// Archive index of entries whose data is stored in the directory file itself
+pub const DIR_ARCHIVE_INDEX: u16 = 0x7fff;
+const ENTRY_TERMINATOR: u16 = 0xffff;
+const HEADER_SIZE_V1: u64 = 12;
+const HEADER_SIZE_V2: u64 = 28;
+const OTHER_MD5_SECTION_SIZE: u32 = 48;
+const ARCHIVE_MD5_ENTRY_SIZE: u32 = 28;
+
+#[derive(Debug)]
+pub enum VpkError {
+    Io(io::Error),
+    /// The file does not start with the VPK signature
+    InvalidSignature,
+    UnsupportedVersion(u32),
+    /// The directory tree could not be parsed
+    Malformed(String)
+}
+
+impl fmt::Display for VpkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VpkError::Io(err) => write!(f, "{}", err),
+            VpkError::InvalidSignature => write!(f, "Not a VPK file"),
+            VpkError::UnsupportedVersion(version) => write!(f, "Unsupported VPK version {}", version),
+            VpkError::Malformed(reason) => write!(f, "Malformed directory tree: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for VpkError {}
+
+impl From<io::Error> for VpkError {
+    fn from(err: io::Error) -> VpkError {
+        VpkError::Io(err)
+    }
+}
+
+/// A file stored in the VPK
+#[allow(dead_code)]
+pub struct VpkEntry {
+    /// Full path inside the VPK using forward slashes, ie "models/props/barrel.mdl"
+    pub path: String,
+    /// CRC32 of the whole file
+    pub crc: u32,
+    /// Bytes stored directly in the directory tree, before the rest of the data
+    pub preload: Vec<u8>,
+    pub archive_index: u16,
+    /// Offset of the data in its archive. For DIR_ARCHIVE_INDEX this is relative to the end of the tree
+    pub offset: u32,
+    /// Length of the data in its archive, not including the preload bytes
+    pub length: u32
+}
+
+impl VpkEntry {
+    /// Total size of the file once extracted
+    pub fn size(&self) -> u64 {
+        self.preload.len() as u64 + self.length as u64
+    }
+}
+
+/// MD5 of a range of one of the numbered archives, from the version 2 archive MD5 section
+#[allow(dead_code)]
+pub struct ArchiveMd5 {
+    pub archive_index: u32,
+    pub offset: u32,
+    pub length: u32,
+    pub md5: [u8; 16]
+}
+
+/// The checksums version 2 VPKs store after the file data
+#[allow(dead_code)]
+pub struct Checksums {
+    pub archive_md5s: Vec<ArchiveMd5>,
+    pub tree_md5: [u8; 16],
+    pub archive_md5_section_md5: [u8; 16],
+    pub whole_file_md5: [u8; 16]
+}
+
+#[allow(dead_code)]
+pub struct Vpk {
+    /// Path of the directory file
+    pub path: PathBuf,
+    pub version: u32,
+    pub tree_size: u32,
+    pub entries: Vec<VpkEntry>,
+    /// Only present in version 2 VPKs that include an MD5 section
+    pub checksums: Option<Checksums>
+}
+
+#[allow(dead_code)]
+impl Vpk {
+    /// Reads the header and directory tree of a VPK without extracting anything
+    pub fn open(path: &Path) -> Result<Vpk, VpkError> {
+        let mut file = io::BufReader::new(fs::File::open(path)?);
+
+        let mut signature = [0u8; 4];
+        file.read_exact(&mut signature)?;
+        if signature != SIGNATURE {
+            return Err(VpkError::InvalidSignature)
+        }
+        let version = read_u32(&mut file)?;
+        if version != 1 && version != 2 {
+            return Err(VpkError::UnsupportedVersion(version))
+        }
+        let tree_size = read_u32(&mut file)?;
+        //Version 2 adds the sizes of the sections that follow the tree
+        let mut section_sizes = [0u32; 4];
+        if version == 2 {
+            for size in section_sizes.iter_mut() {
+                *size = read_u32(&mut file)?;
+            }
+        }
+        let [file_data_size, archive_md5_size, other_md5_size, _signature_size] = section_sizes;
+
+        //Checked before allocating, so a corrupt size cannot allocate up to 4GB for the tree
+        let header_size = if version == 2 { HEADER_SIZE_V2 } else { HEADER_SIZE_V1 };
+        if header_size + tree_size as u64 > file.get_ref().metadata()?.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("The tree size {} is larger than the file", tree_size)).into())
+        }
+        let mut tree = vec![0u8; tree_size as usize];
+        file.read_exact(&mut tree).map_err(|_| VpkError::Malformed("The tree is cut off".to_string()))?;
+        let entries = parse_tree(&tree)?;
+
+        let mut checksums = None;
+        if version == 2 && other_md5_size == OTHER_MD5_SECTION_SIZE {
+            file.seek(SeekFrom::Start(header_size + tree_size as u64 + file_data_size as u64))?;
+            let mut archive_md5s: Vec<ArchiveMd5> = Vec::with_capacity((archive_md5_size / ARCHIVE_MD5_ENTRY_SIZE) as usize);
+            for _ in 0..archive_md5_size / ARCHIVE_MD5_ENTRY_SIZE {
+                archive_md5s.push(ArchiveMd5 {
+                    archive_index: read_u32(&mut file)?,
+                    offset: read_u32(&mut file)?,
+                    length: read_u32(&mut file)?,
+                    md5: read_md5(&mut file)?
+                });
+            }
+            checksums = Some(Checksums {
+                archive_md5s,
+                tree_md5: read_md5(&mut file)?,
+                archive_md5_section_md5: read_md5(&mut file)?,
+                whole_file_md5: read_md5(&mut file)?
+            });
+        }
+
+        Ok(Vpk {
+            path: path.to_path_buf(),
+            version,
+            tree_size,
+            entries,
+            checksums
+        })
+    }
+
+    /// Gets an entry by its path. Paths are case insensitive, like they are in game
+    pub fn get(&self, path: &str) -> Option<&VpkEntry> {
+        let path = path.replace('\\', "/");
+        self.entries.iter().find(|entry| entry.path.eq_ignore_ascii_case(&path))
+    }
+
+    /// Total size of every file once extracted
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(VpkEntry::size).sum()
+    }
+
+    /// Offset of the file data stored in the directory file itself
+    fn get_data_offset(&self) -> u64 {
+        let header_size = if self.version == 2 { HEADER_SIZE_V2 } else { HEADER_SIZE_V1 };
+        header_size + self.tree_size as u64
+    }
+
+    /// Gets the file holding an entry's data: the directory file, or "<name>_000.vpk" for "<name>_dir.vpk"
+    pub fn get_archive_path(&self, archive_index: u16) -> PathBuf {
+        if archive_index == DIR_ARCHIVE_INDEX {
+            return self.path.clone()
+        }
+        let stem = self.path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        let name = stem.strip_suffix("_dir").unwrap_or(stem);
+        self.path.with_file_name(format!("{}_{:03}.vpk", name, archive_index))
+    }
+
+    /// Reads an entry's contents into memory
+    pub fn read_file(&self, entry: &VpkEntry) -> Result<Vec<u8>, VpkError> {
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        self.copy_file(entry, &mut data)?;
+        Ok(data)
+    }
+
+    /// Writes an entry's contents to `writer` without loading it all into memory
+    pub fn copy_file<W: Write>(&self, entry: &VpkEntry, writer: &mut W) -> Result<(), VpkError> {
+        writer.write_all(&entry.preload)?;
+        if entry.length > 0 {
+            let offset = if entry.archive_index == DIR_ARCHIVE_INDEX {
+                self.get_data_offset() + entry.offset as u64
+            } else {
+                entry.offset as u64
+            };
+            let mut archive = fs::File::open(self.get_archive_path(entry.archive_index))?;
+            archive.seek(SeekFrom::Start(offset))?;
+            let copied = io::copy(&mut archive.take(entry.length as u64), writer)?;
+            if copied != entry.length as u64 {
+                return Err(VpkError::Malformed(format!("The data of {} is cut off", entry.path)))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the directory tree: extensions, containing paths, then filenames, each list ending with an empty string
+fn parse_tree(tree: &[u8]) -> Result<Vec<VpkEntry>, VpkError> {
+    let mut cursor = io::Cursor::new(tree);
+    let mut entries: Vec<VpkEntry> = Vec::new();
+    loop {
+        let extension = read_string(&mut cursor)?;
+        if extension.is_empty() {
+            break
+        }
+        loop {
+            let folder = read_string(&mut cursor)?;
+            if folder.is_empty() {
+                break
+            }
+            loop {
+                let filename = read_string(&mut cursor)?;
+                if filename.is_empty() {
+                    break
+                }
+                //A single space stands for "no folder" or "no extension"
+                let mut path = String::new();
+                if folder != " " {
+                    path.push_str(&folder);
+                    path.push('/');
+                }
+                path.push_str(&filename);
+                if extension != " " {
+                    path.push('.');
+                    path.push_str(&extension);
+                }
+
+                let crc = read_u32(&mut cursor)?;
+                let preload_size = read_u16(&mut cursor)?;
+                let archive_index = read_u16(&mut cursor)?;
+                let offset = read_u32(&mut cursor)?;
+                let length = read_u32(&mut cursor)?;
+                if read_u16(&mut cursor)? != ENTRY_TERMINATOR {
+                    return Err(VpkError::Malformed(format!("Missing terminator after {}", path)))
+                }
+                let mut preload = vec![0u8; preload_size as usize];
+                cursor.read_exact(&mut preload).map_err(|_| VpkError::Malformed(format!("Preload data of {} is cut off", path)))?;
+
+                entries.push(VpkEntry {
+                    path,
+                    crc,
+                    preload,
+                    archive_index,
+                    offset,
+                    length
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn read_string(cursor: &mut io::Cursor<&[u8]>) -> Result<String, VpkError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        cursor.read_exact(&mut byte).map_err(|_| VpkError::Malformed("Unexpected end of tree".to_string()))?;
+        if byte[0] == 0 {
+            break
+        }
+        bytes.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, VpkError> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes).map_err(|_| VpkError::Malformed("Unexpected end of data".to_string()))?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, VpkError> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes).map_err(|_| VpkError::Malformed("Unexpected end of data".to_string()))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_md5<R: Read>(reader: &mut R) -> Result<[u8; 16], VpkError> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes).map_err(|_| VpkError::Malformed("Checksum section is cut off".to_string()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An entry to write into a test tree. Each one gets its own extension and folder block, which the format allows
+    struct TestEntry<'a> {
+        extension: &'a str,
+        folder: &'a str,
+        name: &'a str,
+        crc: u32,
+        preload: &'a [u8],
+        archive_index: u16,
+        offset: u32,
+        length: u32,
+        terminator: u16
+    }
+
+    impl<'a> TestEntry<'a> {
+        fn new(path: (&'a str, &'a str, &'a str), archive_index: u16, offset: u32, length: u32) -> TestEntry<'a> {
+            TestEntry {
+                folder: path.0,
+                name: path.1,
+                extension: path.2,
+                crc: 0xdeadbeef,
+                preload: &[],
+                archive_index,
+                offset,
+                length,
+                terminator: ENTRY_TERMINATOR
+            }
+        }
+    }
+
+    fn push_string(buf: &mut Vec<u8>, value: &str) {
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(0);
+    }
+
+    fn build_tree(entries: &[TestEntry]) -> Vec<u8> {
+        let mut tree: Vec<u8> = Vec::new();
+        for entry in entries {
+            push_string(&mut tree, entry.extension);
+            push_string(&mut tree, entry.folder);
+            push_string(&mut tree, entry.name);
+            tree.extend_from_slice(&entry.crc.to_le_bytes());
+            tree.extend_from_slice(&(entry.preload.len() as u16).to_le_bytes());
+            tree.extend_from_slice(&entry.archive_index.to_le_bytes());
+            tree.extend_from_slice(&entry.offset.to_le_bytes());
+            tree.extend_from_slice(&entry.length.to_le_bytes());
+            tree.extend_from_slice(&entry.terminator.to_le_bytes());
+            tree.extend_from_slice(entry.preload);
+            //End of the filenames, then of the folders
+            tree.push(0);
+            tree.push(0);
+        }
+        tree.push(0);
+        tree
+    }
+
+    fn build_v1(tree: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut vpk: Vec<u8> = SIGNATURE.to_vec();
+        vpk.extend_from_slice(&1u32.to_le_bytes());
+        vpk.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+        vpk.extend_from_slice(tree);
+        vpk.extend_from_slice(data);
+        vpk
+    }
+
+    fn build_v2(tree: &[u8], data: &[u8], archive_md5s: &[u8], other_md5s: &[u8]) -> Vec<u8> {
+        let mut vpk: Vec<u8> = SIGNATURE.to_vec();
+        for value in [2, tree.len(), data.len(), archive_md5s.len(), other_md5s.len(), 0].iter() {
+            vpk.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+        vpk.extend_from_slice(tree);
+        vpk.extend_from_slice(data);
+        vpk.extend_from_slice(archive_md5s);
+        vpk.extend_from_slice(other_md5s);
+        vpk
+    }
+
+    /// Writes the files into a fresh folder in the temp directory, returning the path of the first one
+    fn write_files(test: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("l4d2-vpk-test-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    #[test]
+    fn reads_v1_entries_stored_in_dir_file() {
+        let tree = build_tree(&[
+            TestEntry::new(("models/props", "barrel", "mdl"), DIR_ARCHIVE_INDEX, 0, 5),
+            TestEntry::new((" ", "addoninfo", "txt"), DIR_ARCHIVE_INDEX, 5, 3),
+            TestEntry::new(("scripts", "README", " "), DIR_ARCHIVE_INDEX, 8, 0)
+        ]);
+        let path = write_files("v1", &[("addon.vpk", &build_v1(&tree, b"barrelinf"))]);
+
+        let vpk = Vpk::open(&path).unwrap();
+        assert_eq!(vpk.version, 1);
+        assert_eq!(vpk.tree_size as usize, tree.len());
+        assert!(vpk.checksums.is_none());
+        let paths: Vec<&str> = vpk.entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["models/props/barrel.mdl", "addoninfo.txt", "scripts/README"]);
+        assert_eq!(vpk.entries[0].crc, 0xdeadbeef);
+        assert_eq!(vpk.total_size(), 8);
+
+        let barrel = vpk.get("Models\\Props\\BARREL.mdl").unwrap();
+        assert_eq!(vpk.read_file(barrel).unwrap(), b"barre");
+        assert_eq!(vpk.read_file(vpk.get("addoninfo.txt").unwrap()).unwrap(), b"lin");
+        assert!(vpk.read_file(&vpk.entries[2]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_v2_preload_and_numbered_archives() {
+        let mut preloaded = TestEntry::new(("materials", "skin", "vmt"), DIR_ARCHIVE_INDEX, 4, 2);
+        preloaded.preload = b"pre";
+        let mut preload_only = TestEntry::new(("materials", "tiny", "vmt"), DIR_ARCHIVE_INDEX, 0, 0);
+        preload_only.preload = b"all";
+        let tree = build_tree(&[
+            TestEntry::new(("sound", "scream", "wav"), DIR_ARCHIVE_INDEX, 0, 4),
+            preloaded,
+            preload_only,
+            TestEntry::new(("models", "tank", "mdl"), 0, 3, 6)
+        ]);
+        let dir = build_v2(&tree, b"wav!ld", &[], &[]);
+        let path = write_files("v2", &[("pak01_dir.vpk", &dir), ("pak01_000.vpk", b"xxxtank.mdlyyy")]);
+
+        let vpk = Vpk::open(&path).unwrap();
+        assert_eq!(vpk.version, 2);
+        assert!(vpk.checksums.is_none());
+        assert_eq!(vpk.entries.len(), 4);
+        assert_eq!(vpk.read_file(vpk.get("sound/scream.wav").unwrap()).unwrap(), b"wav!");
+
+        let skin = vpk.get("materials/skin.vmt").unwrap();
+        assert_eq!(skin.size(), 5);
+        assert_eq!(vpk.read_file(skin).unwrap(), b"preld");
+        assert_eq!(vpk.read_file(vpk.get("materials/tiny.vmt").unwrap()).unwrap(), b"all");
+
+        let tank = vpk.get("models/tank.mdl").unwrap();
+        assert_eq!(vpk.get_archive_path(tank.archive_index), path.with_file_name("pak01_000.vpk"));
+        assert_eq!(vpk.read_file(tank).unwrap(), b"tank.m");
+        let mut copied: Vec<u8> = Vec::new();
+        vpk.copy_file(tank, &mut copied).unwrap();
+        assert_eq!(copied, b"tank.m");
+    }
+
+    #[test]
+    fn reads_v2_md5_section() {
+        let tree = build_tree(&[TestEntry::new(("models", "tank", "mdl"), 0, 0, 4)]);
+        let mut archive_md5s: Vec<u8> = Vec::new();
+        for (index, value) in [(0u32, 1u8), (1, 2)].iter() {
+            archive_md5s.extend_from_slice(&index.to_le_bytes());
+            archive_md5s.extend_from_slice(&0u32.to_le_bytes());
+            archive_md5s.extend_from_slice(&1024u32.to_le_bytes());
+            archive_md5s.extend_from_slice(&[*value; 16]);
+        }
+        let mut other_md5s: Vec<u8> = Vec::new();
+        for value in 3..6 {
+            other_md5s.extend_from_slice(&[value; 16]);
+        }
+        let path = write_files("md5", &[("pak01_dir.vpk", &build_v2(&tree, b"", &archive_md5s, &other_md5s))]);
+
+        let vpk = Vpk::open(&path).unwrap();
+        let checksums = vpk.checksums.expect("MD5 section was not read");
+        assert_eq!(checksums.archive_md5s.len(), 2);
+        assert_eq!(checksums.archive_md5s[1].archive_index, 1);
+        assert_eq!(checksums.archive_md5s[1].length, 1024);
+        assert_eq!(checksums.archive_md5s[1].md5, [2; 16]);
+        assert_eq!(checksums.tree_md5, [3; 16]);
+        assert_eq!(checksums.archive_md5_section_md5, [4; 16]);
+        assert_eq!(checksums.whole_file_md5, [5; 16]);
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let mut vpk = build_v1(&build_tree(&[]), b"");
+        vpk[0] = 0;
+        let path = write_files("signature", &[("addon.vpk", &vpk)]);
+        assert!(matches!(Vpk::open(&path), Err(VpkError::InvalidSignature)));
+    }
+
+    #[test]
+    fn rejects_cut_off_tree() {
+        let tree = build_tree(&[TestEntry::new(("models", "tank", "mdl"), DIR_ARCHIVE_INDEX, 0, 0)]);
+        let mut vpk = build_v1(&tree, b"");
+        vpk.truncate(vpk.len() - 10);
+        let path = write_files("cut-off", &[("addon.vpk", &vpk)]);
+        assert!(matches!(Vpk::open(&path), Err(VpkError::Io(err)) if err.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn rejects_tree_size_larger_than_file() {
+        let mut vpk = build_v1(&build_tree(&[]), b"");
+        vpk[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let path = write_files("tree-size", &[("addon.vpk", &vpk)]);
+        assert!(matches!(Vpk::open(&path), Err(VpkError::Io(err)) if err.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn rejects_missing_terminator() {
+        let mut entry = TestEntry::new(("models", "tank", "mdl"), DIR_ARCHIVE_INDEX, 0, 0);
+        entry.terminator = 0x1234;
+        let path = write_files("terminator", &[("addon.vpk", &build_v1(&build_tree(&[entry]), b""))]);
+        match Vpk::open(&path) {
+            Err(VpkError::Malformed(reason)) => assert!(reason.contains("models/tank.mdl"), "{}", reason),
+            _ => panic!("expected a malformed tree")
+        }
+    }
+}